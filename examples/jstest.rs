@@ -1,5 +1,4 @@
 use stick::Port;
-use pasts;
 
 struct AppState {
     running: bool,
@@ -7,7 +6,12 @@ struct AppState {
 }
 
 async fn ctlr_event(state: &mut AppState) {
-    let id = state.port.input().await.unwrap(); // FIXME
+    let (id, _event) = if let Some(input) = state.port.input().await {
+        input
+    } else {
+        state.running = false;
+        return;
+    };
     if let Some(state) = state.port.get(id) {
         println!("{}: {}", id, state);
     }
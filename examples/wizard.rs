@@ -0,0 +1,66 @@
+//! Map an unknown controller by pressing each button when asked, then save
+//! the mapping in stick's format and print it in SDL's format.
+
+use std::time::{Duration, Instant};
+use stick::{Event, Mapping, MappingWizard, Port};
+
+// How long to wait for a control before skipping it.
+const SKIP: Duration = Duration::from_secs(5);
+
+fn main() {
+    let mut port = Port::new();
+    // Get events for every raw input, not just the mapped ones.
+    port.raw_events(true);
+
+    println!("Plug in a controller...");
+    let id = loop {
+        if let Some((id, Event::Connect)) = port.poll_blocking(None) {
+            break id;
+        }
+    };
+    println!("Found {}.  Let go of everything...", port.name(id));
+    std::thread::sleep(Duration::from_secs(1));
+    while port.try_poll().is_some() {}
+
+    let mut wizard = MappingWizard::new(port.get(id).unwrap());
+    println!("Press or move each control (wait to skip one).");
+    while let Some(target) = wizard.target() {
+        println!("  {}?", target);
+        let start = Instant::now();
+        loop {
+            let left = SKIP.checked_sub(start.elapsed());
+            let left = if let Some(left) = left {
+                left
+            } else {
+                println!("  Skipped.");
+                wizard.skip();
+                break;
+            };
+            if let Some((stick, Event::Disconnect)) =
+                port.poll_blocking(Some(left))
+            {
+                if stick == id {
+                    println!("Unplugged!");
+                    return;
+                }
+            }
+            if let Some((_, control)) = wizard.update(port.get(id).unwrap()) {
+                println!("  -> {:?}", control);
+                break;
+            }
+        }
+    }
+
+    let device = port.get(id).unwrap();
+    let mapping = wizard.mapping();
+    if let Some(dir) = Mapping::dir() {
+        match mapping.save_profile(&dir, device.info()) {
+            Ok(()) => println!(
+                "Saved {}",
+                dir.join(Mapping::file_name(device.info())).display()
+            ),
+            Err(e) => println!("Couldn't save the mapping: {}", e),
+        }
+    }
+    println!("{}", mapping.sdl(device.info()));
+}
@@ -0,0 +1,648 @@
+use crate::{Btn, DeviceState, Port, CONTROLLER_MAX};
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+/// An analog input on a controller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    /// Main joystick, left to right.
+    JoyX,
+    /// Main joystick, up to down.
+    JoyY,
+    /// Camera stick, left to right.
+    CamX,
+    /// Camera stick, up to down.
+    CamY,
+    /// Left trigger, released to pressed.
+    TrgL,
+    /// Right trigger, released to pressed.
+    TrgR,
+}
+
+impl Axis {
+    const ALL: [(Axis, &'static str); 6] = [
+        (Axis::JoyX, "JoyX"),
+        (Axis::JoyY, "JoyY"),
+        (Axis::CamX, "CamX"),
+        (Axis::CamY, "CamY"),
+        (Axis::TrgL, "TrgL"),
+        (Axis::TrgR, "TrgR"),
+    ];
+
+    fn value(self, state: &DeviceState) -> f32 {
+        match self {
+            Axis::JoyX => state.joy().map(|j| j.0),
+            Axis::JoyY => state.joy().map(|j| j.1),
+            Axis::CamX => state.cam().map(|c| c.0),
+            Axis::CamY => state.cam().map(|c| c.1),
+            Axis::TrgL => state.lrt().map(|t| t.0),
+            Axis::TrgR => state.lrt().map(|t| t.1),
+        }
+        .unwrap_or(0.0)
+    }
+}
+
+// Names of buttons in config files.
+const BTNS: [(Btn, &str); 16] = [
+    (Btn::Left, "Left"),
+    (Btn::Right, "Right"),
+    (Btn::Up, "Up"),
+    (Btn::Down, "Down"),
+    (Btn::X, "X"),
+    (Btn::A, "A"),
+    (Btn::Y, "Y"),
+    (Btn::B, "B"),
+    (Btn::L, "L"),
+    (Btn::R, "R"),
+    (Btn::W, "W"),
+    (Btn::Z, "Z"),
+    (Btn::F, "F"),
+    (Btn::E, "E"),
+    (Btn::D, "D"),
+    (Btn::C, "C"),
+];
+
+/// What triggers a digital action.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    /// A button is held.
+    Btn(Btn),
+    /// All of the buttons are held at once.
+    Chord(Vec<Btn>),
+    /// An axis is above a value.
+    Above(Axis, f32),
+    /// An axis is below a value.
+    Below(Axis, f32),
+    /// A keyboard key is held (see `Actions::key()`).
+    Key(u32),
+}
+
+impl Binding {
+    fn pressed(&self, state: &DeviceState, keys: &[u32]) -> bool {
+        match *self {
+            Binding::Btn(btn) => state.btn(btn) == Some(true),
+            Binding::Chord(ref btns) => {
+                !btns.is_empty()
+                    && btns.iter().all(|btn| state.btn(*btn) == Some(true))
+            }
+            Binding::Above(axis, value) => axis.value(state) > value,
+            Binding::Below(axis, value) => axis.value(state) < value,
+            Binding::Key(key) => keys.contains(&key),
+        }
+    }
+}
+
+/// What drives an analog action, from -1.0 to 1.0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AxisBinding {
+    /// An axis of the controller.
+    Axis(Axis),
+    /// A pair of digital inputs: the first for -1.0, the second for 1.0.
+    Pair(Binding, Binding),
+}
+
+impl AxisBinding {
+    fn value(&self, state: &DeviceState, keys: &[u32]) -> f32 {
+        match *self {
+            AxisBinding::Axis(axis) => axis.value(state),
+            AxisBinding::Pair(ref neg, ref pos) => {
+                let neg = neg.pressed(state, keys) as i8 as f32;
+                let pos = pos.pressed(state, keys) as i8 as f32;
+                pos - neg
+            }
+        }
+    }
+}
+
+/// Named actions and the inputs bound to them, shared by every player.
+///
+/// Bindings can be saved to and loaded from a config file, one binding per
+/// line, with `//` comments:
+///
+/// ```text
+/// // Digital actions
+/// jump => Y
+/// jump => key 57
+/// sprint => B + L
+/// brake => TrgL > 0.5
+/// // Analog actions
+/// axis steer => JoyX
+/// axis steer => Left / Right
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActionMap {
+    actions: Vec<(String, Vec<Binding>)>,
+    axes: Vec<(String, Vec<AxisBinding>)>,
+}
+
+impl ActionMap {
+    /// Create an empty action map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind an input to a digital action.  An action can have any number of
+    /// bindings, and is pressed when any of them are.
+    pub fn bind(&mut self, action: &str, binding: Binding) -> &mut Self {
+        find_or_insert(&mut self.actions, action).push(binding);
+        self
+    }
+
+    /// Bind an input to an analog action.  When there's more than one
+    /// binding, the one pushed the furthest wins.
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) -> &mut Self {
+        find_or_insert(&mut self.axes, axis).push(binding);
+        self
+    }
+
+    /// Remove all bindings for an action (digital or analog), so that it can
+    /// be rebound.
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.retain(|(name, _)| name != action);
+        self.axes.retain(|(name, _)| name != action);
+    }
+
+    /// Get the bindings for a digital action.
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        find(&self.actions, action).unwrap_or(&[])
+    }
+
+    /// Get the bindings for an analog action.
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        find(&self.axes, axis).unwrap_or(&[])
+    }
+
+    /// Get the names of the digital actions that have bindings.
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Get the names of the analog actions that have bindings.
+    pub fn axes(&self) -> impl Iterator<Item = &str> {
+        self.axes.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Load bindings from a config file.
+    pub fn load<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        text.parse()
+    }
+
+    /// Save bindings to a config file.
+    pub fn save<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+}
+
+impl FromStr for ActionMap {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<Self> {
+        let mut map = ActionMap::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad binding on line {}: {}", number + 1, line),
+                )
+            };
+
+            let mut parts = line.splitn(2, "=>");
+            let name = parts.next().unwrap().trim();
+            let input = parts.next().ok_or_else(error)?.trim();
+
+            if let Some(name) = name.strip_prefix("axis ") {
+                let name = name.trim();
+                let binding = parse_axis_binding(input).ok_or_else(error)?;
+                valid_name(name).ok_or_else(error)?;
+                map.bind_axis(name, binding);
+            } else {
+                let binding = parse_binding(input).ok_or_else(error)?;
+                valid_name(name).ok_or_else(error)?;
+                map.bind(name, binding);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl fmt::Display for ActionMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, bindings) in self.actions.iter() {
+            for binding in bindings {
+                writeln!(f, "{} => {}", name, binding)?;
+            }
+        }
+        for (name, bindings) in self.axes.iter() {
+            for binding in bindings {
+                writeln!(f, "axis {} => {}", name, binding)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Binding::Btn(btn) => write!(f, "{}", btn_name(btn)),
+            Binding::Chord(ref btns) => {
+                let names: Vec<_> = btns.iter().map(|b| btn_name(*b)).collect();
+                write!(f, "{}", names.join(" + "))
+            }
+            Binding::Above(axis, value) => {
+                write!(f, "{} > {:?}", axis_name(axis), value)
+            }
+            Binding::Below(axis, value) => {
+                write!(f, "{} < {:?}", axis_name(axis), value)
+            }
+            Binding::Key(key) => write!(f, "key {}", key),
+        }
+    }
+}
+
+impl fmt::Display for AxisBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            AxisBinding::Axis(axis) => write!(f, "{}", axis_name(axis)),
+            AxisBinding::Pair(ref neg, ref pos) => {
+                write!(f, "{} / {}", neg, pos)
+            }
+        }
+    }
+}
+
+/// The state of every player's actions, for a game to query each frame:
+///
+/// ```norun
+/// let mut actions = Actions::new(ActionMap::load(File::open("keys.txt")?)?);
+/// loop {
+///     port.try_poll();
+///     actions.update(&port);
+///     if actions.just_pressed(0, "jump") {
+///         // ...
+///     }
+///     let steer = actions.axis(0, "steer");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Actions {
+    map: ActionMap,
+    // Controller state for each player, now and at the last update.
+    now: Vec<DeviceState>,
+    before: Vec<DeviceState>,
+    // Keyboard keys held, now and at the last update.
+    keys: Vec<u32>,
+    keys_before: Vec<u32>,
+    // Which player the keyboard controls.
+    keyboard: u8,
+}
+
+impl Actions {
+    /// Create action state for up to `CONTROLLER_MAX` players.
+    pub fn new(map: ActionMap) -> Self {
+        Actions {
+            map,
+            now: vec![DeviceState::default(); CONTROLLER_MAX],
+            before: vec![DeviceState::default(); CONTROLLER_MAX],
+            keys: Vec::new(),
+            keys_before: Vec::new(),
+            keyboard: 0,
+        }
+    }
+
+    /// Get the bindings.
+    pub fn map(&self) -> &ActionMap {
+        &self.map
+    }
+
+    /// Change the bindings.
+    pub fn map_mut(&mut self) -> &mut ActionMap {
+        &mut self.map
+    }
+
+    /// Choose which player the keyboard controls (player 0 by default).
+    pub fn set_keyboard_player(&mut self, player: u8) {
+        self.keyboard = player;
+    }
+
+    /// Report a keyboard key being pressed or released, for `Binding::Key`.
+    /// Stick doesn't read the keyboard, so pass on key codes from your
+    /// windowing library.
+    pub fn key(&mut self, key: u32, pressed: bool) {
+        self.keys.retain(|k| *k != key);
+        if pressed {
+            self.keys.push(key);
+        }
+    }
+
+    /// Read the state of every player's controller from `port`.  Call once
+    /// per frame, before querying actions.
+    pub fn update(&mut self, port: &Port) {
+        for player in 0..CONTROLLER_MAX {
+            let state = port
+                .get(player as u8)
+                .map(|device| device.snapshot())
+                .unwrap_or_default();
+            self.update_player(player as u8, state);
+        }
+    }
+
+    /// Set the state of one player's controller, for when it doesn't come
+    /// from a `Port` (or comes from a `DeviceHandle` on another thread).
+    /// For the keyboard player, this also starts a new frame of key state.
+    pub fn update_player(&mut self, player: u8, state: DeviceState) {
+        let slot = player as usize;
+        if slot >= CONTROLLER_MAX {
+            return;
+        }
+        if player == self.keyboard {
+            self.keys_before.clone_from(&self.keys);
+        }
+        self.before[slot] = self.now[slot];
+        self.now[slot] = state;
+    }
+
+    /// Check if an action is held by a player.
+    pub fn pressed(&self, player: u8, action: &str) -> bool {
+        self.check(player, action, false)
+    }
+
+    /// Check if a player started holding an action since the last update.
+    pub fn just_pressed(&self, player: u8, action: &str) -> bool {
+        self.check(player, action, false) && !self.check(player, action, true)
+    }
+
+    /// Check if a player let go of an action since the last update.
+    pub fn just_released(&self, player: u8, action: &str) -> bool {
+        !self.check(player, action, false) && self.check(player, action, true)
+    }
+
+    /// Get the value of an analog action for a player, from -1.0 to 1.0.
+    pub fn axis(&self, player: u8, axis: &str) -> f32 {
+        let (state, keys) = if let Some(state) = self.state(player, false) {
+            state
+        } else {
+            return 0.0;
+        };
+        self.map
+            .axis_bindings(axis)
+            .iter()
+            .map(|binding| binding.value(state, keys))
+            .fold(0.0, |a, b| if b.abs() > a.abs() { b } else { a })
+    }
+
+    fn check(&self, player: u8, action: &str, before: bool) -> bool {
+        let (state, keys) = if let Some(state) = self.state(player, before) {
+            state
+        } else {
+            return false;
+        };
+        self.map
+            .bindings(action)
+            .iter()
+            .any(|binding| binding.pressed(state, keys))
+    }
+
+    // Get a player's controller state and keys, if there can be a player
+    // with that number.
+    fn state(
+        &self,
+        player: u8,
+        before: bool,
+    ) -> Option<(&DeviceState, &[u32])> {
+        let states = if before { &self.before } else { &self.now };
+        let keys: &[u32] = match (player == self.keyboard, before) {
+            (false, _) => &[],
+            (true, false) => &self.keys,
+            (true, true) => &self.keys_before,
+        };
+        Some((states.get(player as usize)?, keys))
+    }
+}
+
+fn find<'a, T>(list: &'a [(String, Vec<T>)], name: &str) -> Option<&'a [T]> {
+    list.iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, bindings)| &bindings[..])
+}
+
+fn find_or_insert<'a, T>(
+    list: &'a mut Vec<(String, Vec<T>)>,
+    name: &str,
+) -> &'a mut Vec<T> {
+    let index = if let Some(i) = list.iter().position(|(n, _)| n == name) {
+        i
+    } else {
+        list.push((name.to_string(), Vec::new()));
+        list.len() - 1
+    };
+    &mut list[index].1
+}
+
+fn valid_name(name: &str) -> Option<()> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        None
+    } else {
+        Some(())
+    }
+}
+
+fn btn_name(btn: Btn) -> &'static str {
+    BTNS.iter().find(|(b, _)| *b == btn).unwrap().1
+}
+
+fn axis_name(axis: Axis) -> &'static str {
+    Axis::ALL.iter().find(|(a, _)| *a == axis).unwrap().1
+}
+
+fn parse_btn(text: &str) -> Option<Btn> {
+    BTNS.iter().find(|(_, n)| *n == text).map(|(b, _)| *b)
+}
+
+fn parse_axis(text: &str) -> Option<Axis> {
+    Axis::ALL.iter().find(|(_, n)| *n == text).map(|(a, _)| *a)
+}
+
+fn parse_binding(text: &str) -> Option<Binding> {
+    if let Some(key) = text.strip_prefix("key ") {
+        return Some(Binding::Key(key.trim().parse().ok()?));
+    }
+    if let Some(i) = text.find(['>', '<']) {
+        let axis = parse_axis(text[..i].trim())?;
+        let value = text[i + 1..].trim().parse().ok()?;
+        return Some(if text[i..].starts_with('>') {
+            Binding::Above(axis, value)
+        } else {
+            Binding::Below(axis, value)
+        });
+    }
+    if text.contains('+') {
+        let btns = text
+            .split('+')
+            .map(|btn| parse_btn(btn.trim()))
+            .collect::<Option<Vec<_>>>()?;
+        return Some(Binding::Chord(btns));
+    }
+    Some(Binding::Btn(parse_btn(text)?))
+}
+
+fn parse_axis_binding(text: &str) -> Option<AxisBinding> {
+    if let Some(i) = text.find('/') {
+        let neg = parse_binding(text[..i].trim())?;
+        let pos = parse_binding(text[i + 1..].trim())?;
+        return Some(AxisBinding::Pair(neg, pos));
+    }
+    Some(AxisBinding::Axis(parse_axis(text)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockBackend;
+
+    const CONFIG: &str = "\
+// Digital actions
+jump => Y
+jump => key 57
+sprint => B + L
+brake => TrgL > 0.5
+left => JoyX < -0.5
+
+// Analog actions
+axis steer => JoyX
+axis steer => Left / Right
+axis zoom => key 12 / key 13
+";
+
+    fn state(btns: &[Btn], joy: (f32, f32), lrt: (f32, f32)) -> DeviceState {
+        DeviceState {
+            btns: btns.iter().fold(0, |b, btn| b | 1 << *btn as u8),
+            joy,
+            lrt,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let map: ActionMap = CONFIG.parse().unwrap();
+        assert_eq!(
+            map.bindings("jump"),
+            [Binding::Btn(Btn::Y), Binding::Key(57)]
+        );
+        assert_eq!(
+            map.bindings("sprint"),
+            [Binding::Chord(vec![Btn::B, Btn::L])]
+        );
+        assert_eq!(map.bindings("brake"), [Binding::Above(Axis::TrgL, 0.5)]);
+        assert_eq!(map.bindings("left"), [Binding::Below(Axis::JoyX, -0.5)]);
+        let actions: Vec<_> = map.actions().collect();
+        assert_eq!(actions, ["jump", "sprint", "brake", "left"]);
+        assert_eq!(map.axes().collect::<Vec<_>>(), ["steer", "zoom"]);
+        assert_eq!(
+            map.axis_bindings("steer"),
+            [
+                AxisBinding::Axis(Axis::JoyX),
+                AxisBinding::Pair(
+                    Binding::Btn(Btn::Left),
+                    Binding::Btn(Btn::Right)
+                ),
+            ]
+        );
+
+        let mut saved = Vec::new();
+        map.save(&mut saved).unwrap();
+        assert_eq!(ActionMap::load(&saved[..]).unwrap(), map);
+
+        assert!("jump => Q".parse::<ActionMap>().is_err());
+        assert!("jump Y".parse::<ActionMap>().is_err());
+        assert!("axis steer => Joy".parse::<ActionMap>().is_err());
+    }
+
+    #[test]
+    fn actions() {
+        let mut actions = Actions::new(CONFIG.parse().unwrap());
+
+        actions.update_player(
+            1,
+            state(&[Btn::Y, Btn::B], (-0.75, 0.0), (0.6, 0.0)),
+        );
+        assert!(actions.pressed(1, "jump"));
+        assert!(actions.just_pressed(1, "jump"));
+        assert!(!actions.pressed(0, "jump"));
+        // Chords need every button.
+        assert!(!actions.pressed(1, "sprint"));
+        assert!(actions.pressed(1, "brake"));
+        // Comparisons are strict.
+        actions.update_player(1, state(&[], (-0.5, 0.0), (0.5, 0.0)));
+        assert!(!actions.pressed(1, "brake"));
+        assert!(!actions.pressed(1, "left"));
+        actions.update_player(
+            1,
+            state(&[Btn::Y, Btn::B], (-0.75, 0.0), (0.6, 0.0)),
+        );
+        assert!(actions.pressed(1, "left"));
+        assert_eq!(actions.axis(1, "steer"), -0.75);
+
+        actions.update_player(
+            1,
+            state(
+                &[Btn::Y, Btn::B, Btn::L, Btn::Right],
+                (0.25, 0.0),
+                (0.0, 0.0),
+            ),
+        );
+        assert!(actions.pressed(1, "jump"));
+        assert!(!actions.just_pressed(1, "jump"));
+        assert!(actions.just_pressed(1, "sprint"));
+        assert!(actions.just_released(1, "brake"));
+        // The D-pad beats the stick, because it's pushed further.
+        assert_eq!(actions.axis(1, "steer"), 1.0);
+        assert_eq!(actions.axis(1, "unbound"), 0.0);
+
+        // Players past `CONTROLLER_MAX` never have anything held.
+        let last = u8::MAX;
+        actions.update_player(last, state(&[Btn::Y], (1.0, 0.0), (0.0, 0.0)));
+        assert!(!actions.pressed(last, "jump"));
+        assert!(!actions.just_released(last, "jump"));
+        assert_eq!(actions.axis(last, "steer"), 0.0);
+    }
+
+    #[test]
+    fn keyboard() {
+        let mut actions = Actions::new(CONFIG.parse().unwrap());
+        actions.set_keyboard_player(2);
+
+        actions.key(57, true);
+        actions.key(13, true);
+        assert!(actions.pressed(2, "jump"));
+        assert!(!actions.pressed(0, "jump"));
+        assert_eq!(actions.axis(2, "zoom"), 1.0);
+
+        // Key state carries over to the next frame.
+        let port = Port::with_backend(MockBackend::new());
+        actions.update(&port);
+        assert!(!actions.just_pressed(2, "jump"));
+
+        actions.key(12, true);
+        assert_eq!(actions.axis(2, "zoom"), 0.0);
+        actions.key(57, false);
+        assert!(actions.just_released(2, "jump"));
+
+        // Without a `Port`, keys roll over with the keyboard player's state.
+        actions.update_player(2, DeviceState::default());
+        assert!(!actions.just_released(2, "jump"));
+        actions.key(57, true);
+        assert!(actions.just_pressed(2, "jump"));
+    }
+}
@@ -0,0 +1,369 @@
+use crate::devices::DeviceInfo;
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Waker};
+use std::time::{Duration, Instant};
+
+/// Raw input from a [`Backend`](trait.Backend.html), before it's mapped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawInput {
+    /// A device was plugged in.  The `usize` identifies the device in the
+    /// backend, and can be reused once it's unplugged.
+    Connect(usize, DeviceInfo),
+    /// A device was unplugged.
+    Disconnect(usize),
+    /// An evdev event from a device: type (`EV_*`), code and value.
+    Event(usize, u16, u16, i32),
+}
+
+/// A source of raw controller input for a `Port`.  `Port::new()` uses the
+/// native backend for the platform; use `Port::with_backend()` to use another
+/// one, like [`MockBackend`](struct.MockBackend.html) in tests.
+pub trait Backend: Send {
+    /// Add input that's ready to `input`, waiting up to `timeout` for some if
+    /// there isn't any (`None` waits forever).  Backends may return less
+    /// than everything that's ready, as long as it's at least one frame.
+    fn poll(&mut self, timeout: Option<Duration>, input: &mut Vec<RawInput>);
+
+    /// Arrange for the task to be woken when there's input.  Returns `true`
+    /// if there may be input already, and the caller should poll again.
+    fn wake_on_input(&mut self, cx: &mut Context<'_>) -> bool;
+
+    /// Check if there will never be any more input, like at the end of a
+    /// recording.  The `Port`'s `Stream` ends then.
+    fn finished(&mut self) -> bool {
+        false
+    }
+
+    /// Send an evdev event to a device, such as force feedback (`EV_FF`) or
+    /// an LED (`EV_LED`).  Backends that can't fail with `Unsupported`.
+    fn write(
+        &mut self,
+        native: usize,
+        ev_type: u16,
+        code: u16,
+        value: i32,
+    ) -> io::Result<()> {
+        let _ = (native, ev_type, code, value);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Backend can't send events to devices",
+        ))
+    }
+
+    /// Rumble a device's strong (low frequency) and weak (high frequency)
+    /// motors for `duration`.  Both at 0 stops it.  Backends that can't fail
+    /// with `Unsupported`.
+    fn rumble(
+        &mut self,
+        native: usize,
+        strong: u16,
+        weak: u16,
+        duration: Duration,
+    ) -> io::Result<()> {
+        let _ = (native, strong, weak, duration);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Backend can't rumble devices",
+        ))
+    }
+
+    /// Set the color of a device's light, such as a PlayStation controller's
+    /// lightbar.  Backends that can't fail with `Unsupported`.
+    fn set_light(
+        &mut self,
+        native: usize,
+        r: u8,
+        g: u8,
+        b: u8,
+    ) -> io::Result<()> {
+        let _ = (native, r, g, b);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Backend can't set device lights",
+        ))
+    }
+}
+
+/// An in-memory backend for testing code that uses a `Port` without real
+/// devices.  Clone it before passing it to `Port::with_backend()`, and use
+/// the clone to script input:
+///
+/// ```norun
+/// let mock = MockBackend::new();
+/// let mut port = Port::with_backend(mock.clone());
+/// let pad = mock.connect(DeviceInfo::default());
+/// mock.key(pad, 0x130, true);
+/// assert_eq!(port.try_poll(), Some((0, Event::Connect)));
+/// ```
+#[derive(Clone, Default)]
+pub struct MockBackend(Arc<(Mutex<Mock>, Condvar)>);
+
+#[derive(Default)]
+struct Mock {
+    // Input that the `Port` hasn't polled yet.
+    queue: VecDeque<RawInput>,
+    // Which device ids are plugged in.
+    plugged: Vec<bool>,
+    // Task to wake on new input.
+    waker: Option<Waker>,
+    // Events sent to each device.
+    output: Vec<Vec<(u16, u16, i32)>>,
+    // Last rumble sent to each device.
+    rumble: Vec<Option<Rumble>>,
+    // Color of each device's light.
+    light: Vec<Option<(u8, u8, u8)>>,
+}
+
+// Strong and weak motor strength, and how long for.
+type Rumble = (u16, u16, Duration);
+
+impl MockBackend {
+    /// Create a new mock backend with no devices plugged in.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plug in a device, returning the id to script its input with.
+    pub fn connect(&self, info: DeviceInfo) -> usize {
+        self.push(|mock| {
+            let id = if let Some(id) = mock.plugged.iter().position(|p| !p) {
+                id
+            } else {
+                mock.plugged.push(false);
+                mock.plugged.len() - 1
+            };
+            mock.plugged[id] = true;
+            mock.output.resize(mock.plugged.len(), Vec::new());
+            mock.output[id].clear();
+            mock.rumble.resize(mock.plugged.len(), None);
+            mock.rumble[id] = None;
+            mock.light.resize(mock.plugged.len(), None);
+            mock.light[id] = None;
+            mock.queue.push_back(RawInput::Connect(id, info));
+            id
+        })
+    }
+
+    /// Unplug a device.
+    pub fn disconnect(&self, id: usize) {
+        self.push(|mock| {
+            mock.plugged[id] = false;
+            mock.queue.push_back(RawInput::Disconnect(id));
+        })
+    }
+
+    /// Send a raw evdev event, without ending the frame.
+    pub fn event(&self, id: usize, ev_type: u16, code: u16, value: i32) {
+        self.push(|mock| {
+            mock.queue
+                .push_back(RawInput::Event(id, ev_type, code, value));
+        })
+    }
+
+    /// End the frame (`SYN_REPORT`), so that the `Port` applies the events
+    /// sent since the last one.
+    pub fn sync(&self, id: usize) {
+        self.event(id, 0x00, 0, 0);
+    }
+
+    /// Press (`true`) or release a button (`EV_KEY` code), in its own frame.
+    pub fn key(&self, id: usize, code: u16, pressed: bool) {
+        self.event(id, 0x01, code, pressed as i32);
+        self.sync(id);
+    }
+
+    /// Move an absolute axis (`EV_ABS` code), in its own frame.
+    pub fn abs(&self, id: usize, code: u16, value: i32) {
+        self.event(id, 0x03, code, value);
+        self.sync(id);
+    }
+
+    /// Get the events sent to a device (see `Backend::write()`) since it was
+    /// plugged in: type, code and value.
+    pub fn output(&self, id: usize) -> Vec<(u16, u16, i32)> {
+        let mock = (self.0).0.lock().unwrap();
+        mock.output.get(id).cloned().unwrap_or_default()
+    }
+
+    /// Get the last rumble sent to a device (see `Backend::rumble()`): strong
+    /// and weak motor strength, and duration.
+    pub fn rumbling(&self, id: usize) -> Option<(u16, u16, Duration)> {
+        let mock = (self.0).0.lock().unwrap();
+        mock.rumble.get(id).cloned().flatten()
+    }
+
+    /// Get the color a device's light was set to (see
+    /// `Backend::set_light()`).
+    pub fn light(&self, id: usize) -> Option<(u8, u8, u8)> {
+        let mock = (self.0).0.lock().unwrap();
+        mock.light.get(id).cloned().flatten()
+    }
+
+    fn push<T>(&self, f: impl FnOnce(&mut Mock) -> T) -> T {
+        let (ref mock, ref condvar) = *self.0;
+        let mut mock = mock.lock().unwrap();
+        let ret = f(&mut mock);
+
+        if let Some(waker) = mock.waker.take() {
+            waker.wake();
+        }
+        condvar.notify_all();
+
+        ret
+    }
+}
+
+impl Backend for MockBackend {
+    fn poll(&mut self, timeout: Option<Duration>, input: &mut Vec<RawInput>) {
+        let (ref mock, ref condvar) = *self.0;
+        let mut mock = mock.lock().unwrap();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        while mock.queue.is_empty() {
+            mock = if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return;
+                }
+                condvar.wait_timeout(mock, deadline - now).unwrap().0
+            } else {
+                condvar.wait(mock).unwrap()
+            };
+        }
+
+        // One frame at a time, so state can be checked after each.
+        while let Some(raw) = mock.queue.pop_front() {
+            let end = match raw {
+                RawInput::Event(_, ev_type, ev_code, _) => {
+                    ev_type == 0x00 && ev_code == 0
+                }
+                _ => true,
+            };
+            input.push(raw);
+            if end {
+                break;
+            }
+        }
+    }
+
+    fn write(
+        &mut self,
+        native: usize,
+        ev_type: u16,
+        code: u16,
+        value: i32,
+    ) -> io::Result<()> {
+        let mut mock = (self.0).0.lock().unwrap();
+        if !mock.plugged.get(native).cloned().unwrap_or(false) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Device is unplugged",
+            ));
+        }
+        mock.output[native].push((ev_type, code, value));
+        Ok(())
+    }
+
+    fn rumble(
+        &mut self,
+        native: usize,
+        strong: u16,
+        weak: u16,
+        duration: Duration,
+    ) -> io::Result<()> {
+        let mut mock = (self.0).0.lock().unwrap();
+        if !mock.plugged.get(native).cloned().unwrap_or(false) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Device is unplugged",
+            ));
+        }
+        mock.rumble[native] = Some((strong, weak, duration));
+        Ok(())
+    }
+
+    fn set_light(
+        &mut self,
+        native: usize,
+        r: u8,
+        g: u8,
+        b: u8,
+    ) -> io::Result<()> {
+        let mut mock = (self.0).0.lock().unwrap();
+        if !mock.plugged.get(native).cloned().unwrap_or(false) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Device is unplugged",
+            ));
+        }
+        mock.light[native] = Some((r, g, b));
+        Ok(())
+    }
+
+    fn wake_on_input(&mut self, cx: &mut Context<'_>) -> bool {
+        let mut mock = (self.0).0.lock().unwrap();
+
+        if mock.queue.is_empty() {
+            mock.waker = Some(cx.waker().clone());
+            false
+        } else {
+            true
+        }
+    }
+}
+
+// A `Port` on a new `MockBackend`, and the mock to script its input with.
+#[cfg(test)]
+pub(crate) fn mock_port() -> (MockBackend, crate::Port) {
+    let mock = MockBackend::new();
+    (mock.clone(), crate::Port::with_backend(mock))
+}
+
+// Poll a `Port` until there's no more input, returning the events.
+#[cfg(test)]
+pub(crate) fn drain(port: &mut crate::Port) -> Vec<(u8, crate::Event)> {
+    std::iter::from_fn(|| port.try_poll()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AbsInfo, Btn, Event, Port};
+
+    #[test]
+    fn mock_port() {
+        let mock = MockBackend::new();
+        let mut port = Port::with_backend(mock.clone());
+        assert_eq!(port.try_poll(), None);
+
+        let pad = mock.connect(DeviceInfo {
+            hardware_id: 0x_054C_0268,
+            name: "Sony PLAYSTATION(R)3 Controller".to_string(),
+            uniq: String::new(),
+            path: String::new(),
+            abs: vec![(
+                0,
+                AbsInfo {
+                    min: 0,
+                    max: 255,
+                    ..Default::default()
+                },
+            )],
+            keys: vec![0x130],
+        });
+        mock.key(pad, 0x130, true);
+        mock.key(pad, 0x130, false);
+        assert_eq!(port.try_poll(), Some((0, Event::Connect)));
+        assert_eq!(port.try_poll(), Some((0, Event::Cancel(true))));
+        assert_eq!(port.get(0).unwrap().btn(Btn::B), Some(true));
+        assert_eq!(port.try_poll(), Some((0, Event::Cancel(false))));
+        assert_eq!(port.get(0).unwrap().btn(Btn::B), Some(false));
+
+        mock.disconnect(pad);
+        assert_eq!(port.try_poll(), Some((0, Event::Disconnect)));
+        assert_eq!(port.count(), 0);
+    }
+}
@@ -0,0 +1,386 @@
+//! Turns controller input into keyboard keys, mouse motion and scrolling,
+//! through a uinput device, for using a desktop from the couch.
+//!
+//! `stick-mapper [CONFIG]` reads an `ActionMap` config file (by default
+//! `$XDG_CONFIG_HOME/stick/mapper.txt`, written with the default bindings if
+//! it doesn't exist).  Its digital actions are named after the keys
+//! (`KEY_ENTER`, `KEY_1` or `KEY_#28` by code) and mouse buttons (`BTN_LEFT`,
+//! `BTN_RIGHT`, `BTN_MIDDLE`) they press, and its analog actions after what
+//! they move: the pointer (`REL_X`, `REL_Y`) or the scroll wheels
+//! (`REL_WHEEL`, `REL_HWHEEL`).  Every controller drives the same keyboard
+//! and mouse.  Writing to `/dev/uinput` usually needs root or a udev rule.
+
+#[cfg(target_os = "linux")]
+fn main() {
+    if let Err(e) = linux::run() {
+        eprintln!("stick-mapper: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("stick-mapper: only Linux has uinput");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, Write};
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+    use std::time::{Duration, Instant};
+    use stick::{ActionMap, Actions, Port, CONTROLLER_MAX};
+
+    // The bindings when there's no config file, following the keyboard
+    // equivalents in the `Btn` docs.
+    const DEFAULT: &str = "\
+// Keys (KEY_ENTER, KEY_1, KEY_#28 by code...) and mouse buttons (BTN_LEFT,
+// BTN_RIGHT, BTN_MIDDLE)
+KEY_LEFT => Left
+KEY_RIGHT => Right
+KEY_UP => Up
+KEY_DOWN => Down
+BTN_LEFT => X
+KEY_ENTER => A
+KEY_SPACE => Y
+KEY_LEFTSHIFT => B
+KEY_LEFTCTRL => L
+KEY_LEFTALT => R
+KEY_BACKSPACE => W
+BTN_RIGHT => Z
+KEY_ESC => F
+KEY_TAB => E
+BTN_MIDDLE => D
+KEY_F => C
+
+// Pointer (REL_X, REL_Y) and scrolling (REL_WHEEL, REL_HWHEEL), where down
+// and right are positive like the sticks
+axis REL_X => CamX
+axis REL_Y => CamY
+axis REL_WHEEL => JoyY
+axis REL_HWHEEL => JoyX
+";
+
+    // How often to move the pointer.
+    const FRAME: Duration = Duration::from_millis(8);
+    // Pixels per second for the pointer, and notches per second for the
+    // scroll wheels, at full tilt.
+    const POINTER_SPEED: f32 = 1200.0;
+    const SCROLL_SPEED: f32 = 12.0;
+
+    const EV_SYN: u16 = 0x00;
+    const EV_KEY: u16 = 0x01;
+    const EV_REL: u16 = 0x02;
+    const BTN_LEFT: u16 = 0x110;
+    const BTN_RIGHT: u16 = 0x111;
+    const BTN_MIDDLE: u16 = 0x112;
+    const REL_X: u16 = 0x00;
+    const REL_Y: u16 = 0x01;
+    const REL_HWHEEL: u16 = 0x06;
+    const REL_WHEEL: u16 = 0x08;
+
+    // Linux key codes, by name after `KEY_`.
+    const KEYS: [(&str, u16); 60] = [
+        ("ESC", 1),
+        ("1", 2),
+        ("2", 3),
+        ("3", 4),
+        ("4", 5),
+        ("5", 6),
+        ("6", 7),
+        ("7", 8),
+        ("8", 9),
+        ("9", 10),
+        ("0", 11),
+        ("BACKSPACE", 14),
+        ("TAB", 15),
+        ("ENTER", 28),
+        ("LEFTCTRL", 29),
+        ("LEFTSHIFT", 42),
+        ("LEFTALT", 56),
+        ("SPACE", 57),
+        ("HOME", 102),
+        ("UP", 103),
+        ("PAGEUP", 104),
+        ("LEFT", 105),
+        ("RIGHT", 106),
+        ("END", 107),
+        ("DOWN", 108),
+        ("PAGEDOWN", 109),
+        ("DELETE", 111),
+        ("MUTE", 113),
+        ("VOLUMEDOWN", 114),
+        ("VOLUMEUP", 115),
+        ("LEFTMETA", 125),
+        ("MENU", 139),
+        ("BACK", 158),
+        ("FORWARD", 159),
+        ("A", 30),
+        ("B", 48),
+        ("C", 46),
+        ("D", 32),
+        ("E", 18),
+        ("F", 33),
+        ("G", 34),
+        ("H", 35),
+        ("I", 23),
+        ("J", 36),
+        ("K", 37),
+        ("L", 38),
+        ("M", 50),
+        ("N", 49),
+        ("O", 24),
+        ("P", 25),
+        ("Q", 16),
+        ("R", 19),
+        ("S", 31),
+        ("T", 20),
+        ("U", 22),
+        ("V", 47),
+        ("W", 17),
+        ("X", 45),
+        ("Y", 21),
+        ("Z", 44),
+    ];
+
+    // A raw evdev event (`struct input_event`).
+    #[repr(C)]
+    struct InputEvent {
+        ev_time: [isize; 2],
+        ev_type: u16,
+        ev_code: u16,
+        ev_value: i32,
+    }
+
+    // `struct uinput_setup`.
+    #[repr(C)]
+    struct UinputSetup {
+        // Bus type, vendor, product and version.
+        id: [u16; 4],
+        name: [u8; 80],
+        ff_effects_max: u32,
+    }
+
+    // A virtual keyboard and mouse.
+    struct Uinput(File);
+
+    impl Uinput {
+        // Create the device, with the keys and buttons it can press.
+        fn new(keys: &[u16]) -> io::Result<Self> {
+            extern "C" {
+                fn ioctl(fd: i32, request: usize, ...) -> i32;
+            }
+
+            let file = OpenOptions::new().write(true).open("/dev/uinput")?;
+            let fd = file.as_raw_fd();
+            let check = |ret: i32| {
+                if ret == -1 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            };
+
+            // UI_SET_EVBIT, UI_SET_KEYBIT and UI_SET_RELBIT
+            let (evbit, keybit, relbit) =
+                (0x4004_5564, 0x4004_5565, 0x4004_5566);
+            for ev in [EV_KEY, EV_REL].iter() {
+                check(unsafe { ioctl(fd, evbit, i32::from(*ev)) })?;
+            }
+            // Always a mouse, so the pointer shows up.
+            let buttons = [BTN_LEFT, BTN_RIGHT, BTN_MIDDLE];
+            for key in keys.iter().chain(buttons.iter()) {
+                check(unsafe { ioctl(fd, keybit, i32::from(*key)) })?;
+            }
+            for rel in [REL_X, REL_Y, REL_HWHEEL, REL_WHEEL].iter() {
+                check(unsafe { ioctl(fd, relbit, i32::from(*rel)) })?;
+            }
+
+            let mut setup = UinputSetup {
+                // BUS_VIRTUAL
+                id: [0x06, 0, 0, 1],
+                name: [0; 80],
+                ff_effects_max: 0,
+            };
+            let name = b"stick-mapper";
+            setup.name[..name.len()].copy_from_slice(name);
+            // UI_DEV_SETUP: _IOW('U', 3, struct uinput_setup)
+            let request = 0x4000_5503 | mem::size_of::<UinputSetup>() << 16;
+            check(unsafe { ioctl(fd, request, &setup as *const UinputSetup) })?;
+            // UI_DEV_CREATE
+            check(unsafe { ioctl(fd, 0x5501) })?;
+
+            Ok(Uinput(file))
+        }
+
+        fn send(&mut self, ev_type: u16, ev_code: u16, ev_value: i32) {
+            let ev = InputEvent {
+                ev_time: [0; 2],
+                ev_type,
+                ev_code,
+                ev_value,
+            };
+            let size = mem::size_of::<InputEvent>();
+            let ptr = &ev as *const InputEvent as *const u8;
+            let bytes = unsafe { std::slice::from_raw_parts(ptr, size) };
+            // A dropped event isn't worth stopping for.
+            let _ = self.0.write_all(bytes);
+        }
+    }
+
+    impl Drop for Uinput {
+        fn drop(&mut self) {
+            extern "C" {
+                fn ioctl(fd: i32, request: usize, ...) -> i32;
+            }
+
+            // UI_DEV_DESTROY
+            unsafe { ioctl(self.0.as_raw_fd(), 0x5502) };
+        }
+    }
+
+    // Get the key or button code of a digital action.
+    fn key(name: &str) -> Option<u16> {
+        match name {
+            "BTN_LEFT" => return Some(BTN_LEFT),
+            "BTN_RIGHT" => return Some(BTN_RIGHT),
+            "BTN_MIDDLE" => return Some(BTN_MIDDLE),
+            _ => {}
+        }
+        let name = name.strip_prefix("KEY_")?;
+        if let Some(code) = name.strip_prefix('#') {
+            return code.parse().ok().filter(|c| (1..0x100).contains(c));
+        }
+        KEYS.iter().find(|(n, _)| *n == name).map(|(_, code)| *code)
+    }
+
+    // Get the relative axis and speed of an analog action.
+    fn rel(name: &str) -> Option<(u16, f32)> {
+        match name {
+            "REL_X" => Some((REL_X, POINTER_SPEED)),
+            "REL_Y" => Some((REL_Y, POINTER_SPEED)),
+            // The wheel scrolls up for positive values.
+            "REL_WHEEL" => Some((REL_WHEEL, -SCROLL_SPEED)),
+            "REL_HWHEEL" => Some((REL_HWHEEL, SCROLL_SPEED)),
+            _ => None,
+        }
+    }
+
+    // Load the config, writing the default one if there isn't any.
+    fn config() -> io::Result<ActionMap> {
+        if let Some(path) = std::env::args_os().nth(1) {
+            return ActionMap::load(File::open(path)?);
+        }
+
+        let path = stick::config_dir().map(|dir| dir.join("mapper.txt"));
+        match path {
+            Some(path) if path.exists() => ActionMap::load(File::open(path)?),
+            Some(path) => {
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(&path, DEFAULT)?;
+                println!("Wrote the default config to {}", path.display());
+                DEFAULT.parse()
+            }
+            None => DEFAULT.parse(),
+        }
+    }
+
+    pub(super) fn run() -> io::Result<()> {
+        let map = config()?;
+        let invalid = |name: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown key, button or axis: {}", name),
+            )
+        };
+        let mut keys = Vec::new();
+        for name in map.actions() {
+            keys.push((
+                name.to_string(),
+                key(name).ok_or_else(|| invalid(name))?,
+            ));
+        }
+        let mut rels = Vec::new();
+        for name in map.axes() {
+            // Motion that's less than a pixel (or notch) so far.
+            let (code, speed) = rel(name).ok_or_else(|| invalid(name))?;
+            rels.push((name.to_string(), code, speed, 0.0f32));
+        }
+
+        let codes: Vec<u16> = keys.iter().map(|(_, code)| *code).collect();
+        let mut uinput = Uinput::new(&codes)?;
+        let mut port = Port::new();
+        let mut actions = Actions::new(map);
+        let mut held = vec![false; keys.len()];
+        let mut last = Instant::now();
+
+        loop {
+            port.poll_blocking(Some(FRAME));
+            while port.try_poll().is_some() {}
+            actions.update(&port);
+            let now = Instant::now();
+            let dt = (now - last).as_secs_f32();
+            last = now;
+            let players = 0..CONTROLLER_MAX as u8;
+
+            let mut any = false;
+            for ((name, code), held) in keys.iter().zip(held.iter_mut()) {
+                let pressed = players.clone().any(|p| actions.pressed(p, name));
+                if pressed != *held {
+                    uinput.send(EV_KEY, *code, pressed as i32);
+                    *held = pressed;
+                    any = true;
+                }
+            }
+            for (name, code, speed, motion) in rels.iter_mut() {
+                let value: f32 =
+                    players.clone().map(|p| actions.axis(p, name)).sum();
+                let value = value.clamp(-1.0, 1.0);
+                // Slow near the middle, for aiming at small things.
+                *motion += value * value.abs() * *speed * dt;
+                let whole = motion.trunc();
+                if whole != 0.0 {
+                    uinput.send(EV_REL, *code, whole as i32);
+                    *motion -= whole;
+                    any = true;
+                }
+            }
+            if any {
+                uinput.send(EV_SYN, 0, 0);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn names() {
+            assert_eq!(key("KEY_ENTER"), Some(28));
+            assert_eq!(key("KEY_1"), Some(2));
+            assert_eq!(key("KEY_0"), Some(11));
+            assert_eq!(key("KEY_#1"), Some(1));
+            assert_eq!(key("KEY_#28"), Some(28));
+            assert_eq!(key("KEY_#0"), None);
+            assert_eq!(key("KEY_#256"), None);
+            assert_eq!(key("KEY_28"), None);
+            assert_eq!(key("BTN_RIGHT"), Some(BTN_RIGHT));
+            assert_eq!(key("ENTER"), None);
+
+            assert_eq!(rel("REL_X"), Some((REL_X, POINTER_SPEED)));
+            assert_eq!(rel("REL_WHEEL"), Some((REL_WHEEL, -SCROLL_SPEED)));
+            assert_eq!(rel("REL_Z"), None);
+        }
+
+        #[test]
+        fn default_config() {
+            let map: ActionMap = DEFAULT.parse().unwrap();
+            assert!(map.actions().all(|name| key(name).is_some()));
+            assert!(map.axes().all(|name| rel(name).is_some()));
+            assert!("KEY_#57 => Y".parse::<ActionMap>().is_ok());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use crate::backend::Backend;
+use crate::devices::{DeviceClass, DeviceInfo, Port};
+use crate::{Calibration, Mapping, NativeManager};
+
+use std::path::PathBuf;
+
+// Decides whether a `Port` takes a device.
+pub(crate) type Filter = Box<dyn Fn(&DeviceInfo) -> bool + Send>;
+
+/// Builds a `Port` that only takes some devices, so that different parts of
+/// a program can each have their own.  Devices have to pass every rule:
+///
+/// ```norun
+/// // The flight stick, and every gamepad except the Xbox ones.
+/// let flight = Port::builder().include(0x07B5, Some(0x0316)).build();
+/// let pads = Port::builder()
+///     .class(DeviceClass::Gamepad)
+///     .exclude(0x045E, None)
+///     .build();
+/// ```
+pub struct PortBuilder {
+    backend: Option<Box<dyn Backend>>,
+    // Whether the backend is the platform's devices.
+    native: bool,
+    // Where to load calibration profiles and mappings from, if not the
+    // default.
+    calibration_dir: Option<Option<PathBuf>>,
+    mapping_dir: Option<Option<PathBuf>>,
+    filters: Vec<Filter>,
+    // Vendor and (optional) product IDs to take.
+    include: Vec<(u16, Option<u16>)>,
+    classes: Vec<DeviceClass>,
+    paths: Vec<String>,
+}
+
+impl PortBuilder {
+    pub(crate) fn new() -> Self {
+        PortBuilder {
+            backend: None,
+            native: true,
+            calibration_dir: None,
+            mapping_dir: None,
+            filters: Vec::new(),
+            include: Vec::new(),
+            classes: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Get input from a backend other than the native one, such as a
+    /// [`MockBackend`](struct.MockBackend.html).
+    pub fn backend<B: Backend + 'static>(mut self, backend: B) -> Self {
+        self.backend = Some(Box::new(backend));
+        self.native = false;
+        self
+    }
+
+    // Get input from the platform's devices, opened some other way.
+    #[cfg(all(not(target_os = "macos"), unix))]
+    pub(crate) fn native_backend(mut self, backend: NativeManager) -> Self {
+        self.backend = Some(Box::new(backend));
+        self
+    }
+
+    /// Load calibration profiles from `dir` when devices are plugged in, or
+    /// not at all if `None`.  By default they're loaded from
+    /// `Calibration::dir()` for the platform's devices, and not for other
+    /// backends.
+    pub fn calibration_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.calibration_dir = Some(dir);
+        self
+    }
+
+    /// Load mappings from `dir` when devices are plugged in, or not at all
+    /// if `None`.  By default they're loaded from `Mapping::dir()` for the
+    /// platform's devices, and not for other backends.
+    pub fn mapping_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.mapping_dir = Some(dir);
+        self
+    }
+
+    /// Only take devices that `filter` returns `true` for.
+    pub fn filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&DeviceInfo) -> bool + Send + 'static,
+    {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Take devices with a vendor ID, and a product ID (or any product if
+    /// `None`).  Once one is included, only included devices are taken.
+    pub fn include(mut self, vendor: u16, product: Option<u16>) -> Self {
+        self.include.push((vendor, product));
+        self
+    }
+
+    /// Leave out devices with a vendor ID, and a product ID (or any product
+    /// if `None`).
+    pub fn exclude(self, vendor: u16, product: Option<u16>) -> Self {
+        self.filter(move |info| !matches_id(info, vendor, product))
+    }
+
+    /// Take devices of a class.  Once one is added, only devices of the
+    /// added classes are taken.
+    pub fn class(mut self, class: DeviceClass) -> Self {
+        self.classes.push(class);
+        self
+    }
+
+    /// Take the device at a path (see `DeviceInfo::path`).  Once one is
+    /// added, only the listed devices are taken.
+    pub fn path<P: Into<String>>(mut self, path: P) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+
+    /// Create the `Port`, with an `Event::Connect` for each device it takes
+    /// that's already plugged in.
+    pub fn build(self) -> Port {
+        let PortBuilder {
+            backend,
+            native,
+            calibration_dir,
+            mapping_dir,
+            mut filters,
+            include,
+            classes,
+            paths,
+        } = self;
+
+        if !include.is_empty() {
+            filters.push(Box::new(move |info| {
+                include.iter().any(|(v, p)| matches_id(info, *v, *p))
+            }));
+        }
+        if !classes.is_empty() {
+            filters.push(Box::new(move |info| classes.contains(&info.class())));
+        }
+        if !paths.is_empty() {
+            filters.push(Box::new(move |info| paths.contains(&info.path)));
+        }
+        let filter: Option<Filter> = if filters.is_empty() {
+            None
+        } else {
+            Some(Box::new(move |info| filters.iter().all(|f| f(info))))
+        };
+
+        // Profiles on this computer are for its own devices.
+        let calibration_dir = calibration_dir
+            .unwrap_or_else(|| Calibration::dir().filter(|_| native));
+        let mapping_dir =
+            mapping_dir.unwrap_or_else(|| Mapping::dir().filter(|_| native));
+
+        let backend = backend.unwrap_or_else(|| Box::new(NativeManager::new()));
+        Port::build(backend, filter, calibration_dir, mapping_dir)
+    }
+}
+
+// Check if a device has a vendor ID, and a product ID if there is one.
+fn matches_id(info: &DeviceInfo, vendor: u16, product: Option<u16>) -> bool {
+    let id = info.hardware_id;
+    (id >> 16) as u16 == vendor && product.is_none_or(|p| id as u16 == p)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DeviceClass, DeviceInfo, Event, MockBackend, Port};
+
+    // Plug a flight stick, a PS3 pad and an Xbox pad into a port.
+    fn port(builder: crate::PortBuilder) -> Port {
+        let mock = MockBackend::new();
+        let port = builder.backend(mock.clone()).build();
+        let gamepad = vec![0x130, 0x131];
+        for &(hardware_id, path) in [
+            (0x_07B5_0316, "flight"),
+            (0x_054C_0268, "ps3"),
+            (0x_045E_028E, "xbox"),
+        ]
+        .iter()
+        {
+            mock.connect(DeviceInfo {
+                hardware_id,
+                keys: if path == "flight" {
+                    vec![0x120]
+                } else {
+                    gamepad.clone()
+                },
+                path: path.to_string(),
+                ..Default::default()
+            });
+        }
+        port
+    }
+
+    // Get the paths of the devices a port took.
+    fn taken(mut port: Port) -> Vec<String> {
+        let mut paths = Vec::new();
+        while let Some((id, event)) = port.try_poll() {
+            if event == Event::Connect {
+                paths.push(port.get(id).unwrap().info().path.clone());
+            }
+        }
+        paths
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(taken(port(Port::builder())).len(), 3);
+        let flight = Port::builder().include(0x07B5, Some(0x0316));
+        assert_eq!(taken(port(flight)), ["flight"]);
+        let pads = Port::builder()
+            .class(DeviceClass::Gamepad)
+            .exclude(0x045E, None);
+        assert_eq!(taken(port(pads)), ["ps3"]);
+        let listed = Port::builder().path("xbox").path("flight");
+        assert_eq!(taken(port(listed)), ["flight", "xbox"]);
+        let custom = Port::builder().filter(|info| info.keys.len() == 1);
+        assert_eq!(taken(port(custom)), ["flight"]);
+    }
+}
@@ -0,0 +1,490 @@
+use crate::{AxisKind, Device, DeviceInfo};
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Number of slices of a stick's gate measured for its circularity.
+const SECTORS: usize = 16;
+
+// The furthest raw position reached in each slice of a stick's gate.
+type Gate = [Option<(i32, i32)>; SECTORS];
+
+/// The measured range of an axis, in raw values.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AxisRange {
+    /// Lowest value reached.
+    pub min: i32,
+    /// Value at rest.
+    pub center: i32,
+    /// Highest value reached.
+    pub max: i32,
+}
+
+/// Calibration of a device: the measured range of its axes, and how round
+/// the gates of its sticks are.  A `Port` loads each device's calibration
+/// profile when it's plugged in (see `Port::calibration_dir()`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Calibration {
+    axes: Vec<(u8, AxisRange)>,
+    // X and Y `ABS_*` codes, and circularity.
+    sticks: Vec<(u8, u8, f32)>,
+}
+
+impl Calibration {
+    /// Create an empty calibration, which leaves every axis as the device
+    /// reports it.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the range of an axis (`ABS_*` code).
+    pub fn set_axis(&mut self, code: u8, range: AxisRange) {
+        self.axes.retain(|(c, _)| *c != code);
+        self.axes.push((code, range));
+    }
+
+    /// Get the range of an axis (`ABS_*` code), if it's calibrated.
+    pub fn axis(&self, code: u8) -> Option<AxisRange> {
+        self.axes
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, range)| *range)
+    }
+
+    /// Set the circularity of a stick, from its X and Y axes.
+    pub fn set_circularity(&mut self, x: u8, y: u8, circularity: f32) {
+        self.sticks.retain(|(a, b, _)| (*a, *b) != (x, y));
+        self.sticks.push((x, y, circularity));
+    }
+
+    /// Get the circularity of a stick: the average distance from the center
+    /// to the edge of its gate, where the range of each axis is 1.0.  A
+    /// round gate is 1.0, and a square one about 1.15.
+    pub fn circularity(&self, x: u8, y: u8) -> Option<f32> {
+        self.sticks
+            .iter()
+            .find(|(a, b, _)| (*a, *b) == (x, y))
+            .map(|(_, _, circularity)| *circularity)
+    }
+
+    /// Load a calibration from a profile.
+    pub fn load<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        text.parse()
+    }
+
+    /// Save a calibration to a profile.
+    pub fn save<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+
+    /// Get the default directory for calibration profiles:
+    /// `$XDG_CONFIG_HOME/stick/calibration`, or
+    /// `$HOME/.config/stick/calibration`.
+    pub fn dir() -> Option<PathBuf> {
+        Some(crate::config_dir()?.join("calibration"))
+    }
+
+    /// Get the file name of a device's profile, from its vendor ID, product
+    /// ID and unique ID (if it has one), like `046d-c24f.txt`.
+    pub fn file_name(info: &DeviceInfo) -> String {
+        let vendor = info.hardware_id >> 16;
+        let product = info.hardware_id & 0xFFFF;
+        let uniq: String = info
+            .uniq
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if uniq.is_empty() {
+            format!("{:04x}-{:04x}.txt", vendor, product)
+        } else {
+            format!("{:04x}-{:04x}-{}.txt", vendor, product, uniq)
+        }
+    }
+
+    /// Load a device's profile from a directory.  Returns `Ok(None)` if it
+    /// doesn't have one.
+    pub fn load_profile(
+        dir: &Path,
+        info: &DeviceInfo,
+    ) -> io::Result<Option<Self>> {
+        match fs::File::open(dir.join(Self::file_name(info))) {
+            Ok(file) => Self::load(file).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save a device's profile to a directory, creating it if needed.
+    pub fn save_profile(
+        &self,
+        dir: &Path,
+        info: &DeviceInfo,
+    ) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        self.save(fs::File::create(dir.join(Self::file_name(info)))?)
+    }
+}
+
+impl FromStr for Calibration {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<Self> {
+        let mut calibration = Calibration::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad calibration on line {}: {}", number + 1, line),
+                )
+            };
+
+            let mut parts = line.splitn(2, "=>");
+            let name: Vec<_> =
+                parts.next().unwrap().split_whitespace().collect();
+            let values: Vec<_> =
+                parts.next().ok_or_else(error)?.split_whitespace().collect();
+
+            match (name.as_slice(), values.as_slice()) {
+                (["axis", code], [min, center, max]) => {
+                    let range = AxisRange {
+                        min: min.parse().map_err(|_| error())?,
+                        center: center.parse().map_err(|_| error())?,
+                        max: max.parse().map_err(|_| error())?,
+                    };
+                    let code = code.parse().map_err(|_| error())?;
+                    calibration.set_axis(code, range);
+                }
+                (["stick", x, y], [circularity]) => {
+                    calibration.set_circularity(
+                        x.parse().map_err(|_| error())?,
+                        y.parse().map_err(|_| error())?,
+                        circularity.parse().map_err(|_| error())?,
+                    );
+                }
+                _ => return Err(error()),
+            }
+        }
+
+        Ok(calibration)
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (code, range) in self.axes.iter() {
+            writeln!(
+                f,
+                "axis {} => {} {} {}",
+                code, range.min, range.center, range.max
+            )?;
+        }
+        for (x, y, circularity) in self.sticks.iter() {
+            writeln!(f, "stick {} {} => {:?}", x, y, circularity)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measures the axes of a device while the player moves them, to make a
+/// `Calibration`:
+///
+/// ```norun
+/// // Let go of the sticks...
+/// let mut calibrator = Calibrator::new(port.get(id).unwrap());
+/// // ...then roll them around their edges and pull the triggers all the way.
+/// while !done {
+///     port.poll_blocking(None);
+///     calibrator.update(port.get(id).unwrap());
+/// }
+/// let calibration = calibrator.calibration();
+/// calibration.save_profile(&Calibration::dir().unwrap(), &info)?;
+/// port.set_calibration(id, calibration);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Calibrator {
+    axes: Vec<(u8, AxisRange)>,
+    // X and Y `ABS_*` codes, and the gate.
+    sticks: Vec<(u8, u8, Gate)>,
+}
+
+impl Calibrator {
+    /// Start calibrating a device, which should be at rest: where each axis
+    /// is now is taken as its center.
+    pub fn new(device: &Device) -> Self {
+        let info = device.info();
+        let axes = info
+            .abs
+            .iter()
+            // Hats are digital.
+            .filter(|(code, _)| !(0x10..=0x17).contains(code))
+            .map(|(code, _)| {
+                let value = device.raw().abs(*code);
+                let range = AxisRange {
+                    min: value,
+                    center: value,
+                    max: value,
+                };
+                (*code, range)
+            })
+            .collect();
+        let sticks = sticks(info)
+            .into_iter()
+            .map(|(x, y)| (x, y, [None; SECTORS]))
+            .collect();
+
+        Calibrator { axes, sticks }
+    }
+
+    /// Take where each axis is now as its center again.
+    pub fn center(&mut self, device: &Device) {
+        let raw = device.raw();
+        for (code, range) in self.axes.iter_mut() {
+            range.center = raw.abs(*code);
+            range.min = range.min.min(range.center);
+            range.max = range.max.max(range.center);
+        }
+    }
+
+    /// Measure where each axis is now.  Call this every frame while the
+    /// player moves everything as far as it goes.
+    pub fn update(&mut self, device: &Device) {
+        let raw = device.raw();
+        for (code, range) in self.axes.iter_mut() {
+            let value = raw.abs(*code);
+            range.min = range.min.min(value);
+            range.max = range.max.max(value);
+        }
+
+        let axes = &self.axes;
+        for (x, y, sectors) in self.sticks.iter_mut() {
+            let pos = (raw.abs(*x), raw.abs(*y));
+            let (rx, ry) = match (find(axes, *x), find(axes, *y)) {
+                (Some(rx), Some(ry)) => (rx, ry),
+                _ => continue,
+            };
+            let (nx, ny) = (scale(&rx, pos.0), scale(&ry, pos.1));
+            let radius = nx.hypot(ny);
+            if radius < 0.5 {
+                continue;
+            }
+            let sector = sector(nx, ny);
+            let further = sectors[sector].is_none_or(|(ox, oy)| {
+                radius > scale(&rx, ox).hypot(scale(&ry, oy))
+            });
+            if further {
+                sectors[sector] = Some(pos);
+            }
+        }
+    }
+
+    /// Get the calibration from what's been measured so far.  Axes that
+    /// haven't moved and sticks that haven't gone all the way around are
+    /// left out.
+    pub fn calibration(&self) -> Calibration {
+        let mut calibration = Calibration::new();
+        for (code, range) in self.axes.iter() {
+            if range.max > range.min {
+                calibration.set_axis(*code, *range);
+            }
+        }
+        for (x, y, sectors) in self.sticks.iter() {
+            let (rx, ry) = match (calibration.axis(*x), calibration.axis(*y)) {
+                (Some(rx), Some(ry)) => (rx, ry),
+                _ => continue,
+            };
+            // Sort the positions again now that the ranges are known.
+            let mut gate = [0.0f32; SECTORS];
+            for (px, py) in sectors.iter().flatten() {
+                let (nx, ny) = (scale(&rx, *px), scale(&ry, *py));
+                let edge = &mut gate[sector(nx, ny)];
+                *edge = edge.max(nx.hypot(ny));
+            }
+            if gate.contains(&0.0) {
+                continue;
+            }
+            let total: f32 = gate.iter().sum();
+            calibration.set_circularity(*x, *y, total / SECTORS as f32);
+        }
+        calibration
+    }
+}
+
+// Get the X and Y axes of the sticks on a device.
+fn sticks(info: &DeviceInfo) -> Vec<(u8, u8)> {
+    let cam = match info.hardware_id {
+        // GameCube
+        0x_0079_1844 => (5, 2),
+        _ => (3, 4),
+    };
+    [(0, 1), cam]
+        .iter()
+        .cloned()
+        .filter(|(x, y)| {
+            [*x, *y].iter().all(|code| {
+                info.abs(*code).is_some()
+                    && info.axis_kind(*code) == AxisKind::Bipolar
+            })
+        })
+        .collect()
+}
+
+fn find(axes: &[(u8, AxisRange)], code: u8) -> Option<AxisRange> {
+    axes.iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, range)| *range)
+}
+
+// Scale a raw value to -1.0 through 1.0, each side of the center on its own.
+pub(crate) fn scale(range: &AxisRange, value: i32) -> f32 {
+    let offset = (value - range.center) as f32;
+    let half = if value < range.center {
+        range.center - range.min
+    } else {
+        range.max - range.center
+    };
+    if half <= 0 {
+        0.0
+    } else {
+        offset / half as f32
+    }
+}
+
+// Get which slice of the gate a position is in.
+fn sector(x: f32, y: f32) -> usize {
+    let angle = x.atan2(-y) + PI;
+    ((angle / (2.0 * PI) * SECTORS as f32) as usize).min(SECTORS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{drain, mock_port};
+    use crate::{AbsInfo, MockBackend, Port};
+
+    fn pad() -> DeviceInfo {
+        let stick = AbsInfo {
+            value: 128,
+            min: 0,
+            max: 255,
+            ..Default::default()
+        };
+        DeviceInfo {
+            hardware_id: 0x_054C_0268,
+            uniq: "00:11:22:33:44:55".to_string(),
+            abs: vec![(0, stick), (1, stick)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn measure() {
+        let (mock, mut port) = mock_port();
+        let id = mock.connect(pad());
+        // Resting off center.
+        mock.abs(id, 0, 140);
+        mock.abs(id, 1, 120);
+        drain(&mut port);
+
+        let mut calibrator = Calibrator::new(port.get(0).unwrap());
+        // Twice around a round gate that only reaches 20..230 on X.
+        for i in 0..128 {
+            let angle = i as f32 / 64.0 * 2.0 * PI;
+            let (sin, cos) = angle.sin_cos();
+            let x = if sin < 0.0 {
+                140.0 + 120.0 * sin
+            } else {
+                140.0 + 90.0 * sin
+            };
+            mock.abs(id, 0, x.round() as i32);
+            mock.abs(id, 1, (120.0 - 110.0 * cos).round() as i32);
+            drain(&mut port);
+            calibrator.update(port.get(0).unwrap());
+        }
+
+        let calibration = calibrator.calibration();
+        let x = calibration.axis(0).unwrap();
+        assert_eq!((x.min, x.center, x.max), (20, 140, 230));
+        let y = calibration.axis(1).unwrap();
+        assert_eq!((y.min, y.center, y.max), (10, 120, 230));
+        let circularity = calibration.circularity(0, 1).unwrap();
+        assert!((circularity - 1.0).abs() < 0.02, "{}", circularity);
+
+        // Calibrated, the rest position is centered and the edges are full.
+        port.set_calibration(0, calibration);
+        mock.abs(id, 0, 140);
+        mock.abs(id, 1, 120);
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        assert_eq!(device.axis(0), Some(0.0));
+        assert_eq!(device.joy(), Some((0.0, 0.0)));
+        mock.abs(id, 0, 230);
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        assert_eq!(device.axis(0), Some(1.0));
+        assert_eq!(device.joy(), Some((1.0, 0.0)));
+    }
+
+    #[test]
+    fn profiles() {
+        let mut calibration = Calibration::new();
+        calibration.set_axis(
+            0,
+            AxisRange {
+                min: 20,
+                center: 140,
+                max: 230,
+            },
+        );
+        calibration.set_circularity(0, 1, 1.125);
+        let text = calibration.to_string();
+        assert_eq!(text, "axis 0 => 20 140 230\nstick 0 1 => 1.125\n");
+        assert_eq!(text.parse::<Calibration>().unwrap(), calibration);
+        assert!("axis 0 => 20 140".parse::<Calibration>().is_err());
+
+        let info = pad();
+        assert_eq!(
+            Calibration::file_name(&info),
+            "054c-0268-00_11_22_33_44_55.txt"
+        );
+        let dir = std::env::temp_dir()
+            .join(format!("stick-calibration-{}", std::process::id()));
+        assert_eq!(Calibration::load_profile(&dir, &info).unwrap(), None);
+        calibration.save_profile(&dir, &info).unwrap();
+        assert_eq!(
+            Calibration::load_profile(&dir, &info).unwrap(),
+            Some(calibration.clone())
+        );
+
+        // Applied when the device is plugged in.
+        let (mock, mut port) = mock_port();
+        port.calibration_dir(Some(dir.clone()));
+        mock.connect(info);
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().calibration(), calibration);
+
+        // Also for devices already plugged in when the `Port` is built, but
+        // only from a directory that's set.
+        let mock = MockBackend::new();
+        mock.connect(pad());
+        let builder = Port::builder().backend(mock.clone());
+        let mut port = builder.calibration_dir(Some(dir.clone())).build();
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().calibration(), calibration);
+        let mut port = Port::with_backend(mock.clone());
+        mock.connect(pad());
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().calibration(), Calibration::new());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
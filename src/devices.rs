@@ -1,22 +1,215 @@
 use super::NativeManager;
-
-use std::sync::atomic::{AtomicU32, AtomicUsize, AtomicBool, Ordering};
+use crate::backend::{Backend, RawInput};
+use crate::builder::{Filter, PortBuilder};
+use crate::calibrate::{self, AxisRange, Calibration};
+use crate::direction::{Direction, Stick, StickShape};
+use crate::gesture::{Gesture, Recognizer};
+use crate::joycon;
+use crate::mapping::{Control, Mapping, Target};
+use crate::playstation;
+use crate::record::Recorder;
+use crate::timer::Timer;
+use crate::wheel::Wheel;
+use crate::xbox;
+use crate::{Axis, Event};
+
+use futures_core::Stream;
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use std::sync::atomic::{self, AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::{Arc, RwLock};
 
 /// Allow the up to the ridiculous number of 64 physical joysticks.
 pub const CONTROLLER_MAX: usize = 64;
 
-#[repr(C)]
-struct TimeVal {
-    tv_sec: isize,
-    tv_usec: isize,
+// A raw evdev event.
+#[derive(Default, Copy, Clone)]
+pub(crate) struct EvdevEvent {
+    pub(crate) ev_type: i16,
+    pub(crate) ev_code: i16,
+    pub(crate) ev_value: i32,
 }
 
+/// The range of an absolute axis, as reported by the kernel.
 #[repr(C)]
-struct Event {
-    ev_time: TimeVal,
-    ev_type: i16,
-    ev_code: i16,
-    ev_value: i32,
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct AbsInfo {
+    /// Value when the device was plugged in.
+    pub value: i32,
+    /// Minimum value.
+    pub min: i32,
+    /// Maximum value.
+    pub max: i32,
+    /// Noise filtered out by the kernel.
+    pub fuzz: i32,
+    /// Size of the deadzone at the center.
+    pub flat: i32,
+    /// Units per mm (or per radian for rotation axes).
+    pub resolution: i32,
+}
+
+/// Information about a device that doesn't change while it's plugged in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceInfo {
+    /// Vendor ID (high 16 bits) and product ID (low 16 bits).
+    pub hardware_id: u32,
+    /// The name that the device reports.
+    pub name: String,
+    /// The unique ID that the device reports, like a serial number or
+    /// Bluetooth address.  Empty if it doesn't have one.
+    pub uniq: String,
+    /// Absolute axes (`ABS_*` codes) on the device, and their ranges.
+    pub abs: Vec<(u8, AbsInfo)>,
+    /// Buttons and keys (`EV_KEY` codes) on the device.
+    pub keys: Vec<u16>,
+    /// Where the device is, like `/dev/input/by-id/...-event-joystick` on
+    /// Linux.  Empty if the backend doesn't say.
+    pub path: String,
+}
+
+/// What kind of controller a device is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceClass {
+    /// A gamepad, with face buttons and sticks.
+    Gamepad,
+    /// A joystick, such as a flight stick or throttle.
+    Joystick,
+    /// A racing wheel.
+    Wheel,
+}
+
+impl DeviceInfo {
+    /// Get the range of an absolute axis, if the device has it.
+    pub fn abs(&self, code: u8) -> Option<AbsInfo> {
+        self.abs
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, info)| *info)
+    }
+
+    /// Get the number of hats (`ABS_HAT0X` through `ABS_HAT3Y`) on the
+    /// device.
+    pub fn hats(&self) -> u8 {
+        (0..4)
+            .filter(|hat| self.abs(ABS_HAT0X + hat * 2).is_some())
+            .count() as u8
+    }
+
+    /// Get how an absolute axis moves.  This comes from the mapping of known
+    /// controllers, or else from where a trigger, throttle or pedal rested
+    /// when the device was plugged in.  Other axes are `Bipolar`.
+    pub fn axis_kind(&self, code: u8) -> AxisKind {
+        if let Some(kind) = mapped_kind(self.hardware_id, code) {
+            return kind;
+        }
+        if !matches!(code, ABS_Z | ABS_RZ | ABS_THROTTLE | ABS_GAS | ABS_BRAKE)
+        {
+            return AxisKind::Bipolar;
+        }
+        let abs = match self.abs(code) {
+            Some(abs) if abs.max > abs.min => abs,
+            _ => return AxisKind::Unipolar,
+        };
+        let rest = (abs.value - abs.min) as f32 / (abs.max - abs.min) as f32;
+        if rest < 0.25 {
+            AxisKind::Unipolar
+        } else if rest > 0.75 {
+            AxisKind::Inverted
+        } else {
+            AxisKind::Bipolar
+        }
+    }
+
+    /// Get what kind of controller the device is: a `Wheel` if it has a
+    /// wheel layout (see `Wheel::new()`), a `Gamepad` if it has the kernel's
+    /// gamepad buttons or a gamepad mapping, otherwise a `Joystick`.
+    pub fn class(&self) -> DeviceClass {
+        if Wheel::new(self).is_some() {
+            DeviceClass::Wheel
+        } else if self.hardware_id == 0x_0079_1844
+            || self.keys.iter().any(|k| (0x130..=0x13E).contains(k))
+        {
+            // The GameCube adapter numbers its buttons like a joystick.
+            DeviceClass::Gamepad
+        } else {
+            DeviceClass::Joystick
+        }
+    }
+
+    /// Get the shape of the gate of the device's sticks, from the built-in
+    /// mapping of known controllers (`Sticks =>`, see `Mapping`).  Others
+    /// are `StickShape::Circle`.
+    pub fn stick_shape(&self) -> StickShape {
+        Mapping::builtin(self.hardware_id)
+            .and_then(|mapping| mapping.stick_shape())
+            .unwrap_or(StickShape::Circle)
+    }
+}
+
+// Axes that can be triggers, throttles or pedals.
+const ABS_Z: u8 = 0x02;
+const ABS_RZ: u8 = 0x05;
+const ABS_THROTTLE: u8 = 0x06;
+const ABS_GAS: u8 = 0x09;
+const ABS_BRAKE: u8 = 0x0A;
+// The Home button (Xbox Guide, PlayStation's PS button).
+const BTN_MODE: u16 = 0x13C;
+// First hat axis; each hat has an X then a Y axis.
+const ABS_HAT0X: u8 = 0x10;
+const ABS_HAT3Y: u8 = 0x17;
+
+// The axis kinds from the mapping of known controllers (`src/controllers/`).
+fn mapped_kind(hardware_id: u32, code: u8) -> Option<AxisKind> {
+    match (hardware_id, code) {
+        // GameCube: triggers are 3 and 4, the camera stick 5 and 2.
+        (0x_0079_1844, 3 | 4) => Some(AxisKind::Unipolar),
+        (0x_0079_1844, 2 | 5) => Some(AxisKind::Bipolar),
+        // DualShock 4 and DualSense triggers.
+        (id, 2 | 5) if playstation::playstation(id) => Some(AxisKind::Unipolar),
+        // Xbox triggers.
+        (id, xbox::ABS_Z | xbox::ABS_RZ) if xbox::xbox(id) => {
+            Some(AxisKind::Unipolar)
+        }
+        // Flight controller: stationary throttle.
+        (0x_07B5_0316, 2) => Some(AxisKind::Unipolar),
+        // Logitech wheels: clutch, throttle and brake rest at the maximum.
+        (
+            0x_046D_C29A | 0x_046D_C299 | 0x_046D_C29B | 0x_046D_C24F
+            | 0x_046D_C262,
+            1 | 2 | 5,
+        ) => Some(AxisKind::Inverted),
+        _ => None,
+    }
+}
+
+/// How an absolute axis moves, which decides how it's scaled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AxisKind {
+    /// Rests in the middle, from -1.0 to 1.0 (sticks).  Sticks get a
+    /// deadzone at the center.
+    Bipolar,
+    /// Rests at the minimum, from 0.0 to 1.0 (triggers, throttles, pedals).
+    Unipolar,
+    /// Rests at the maximum, from 0.0 (at the maximum) to 1.0 (at the
+    /// minimum), like the pedals on some wheels.
+    Inverted,
+}
+
+impl AxisKind {
+    fn from_u8(kind: u8) -> Self {
+        match kind {
+            1 => AxisKind::Unipolar,
+            2 => AxisKind::Inverted,
+            _ => AxisKind::Bipolar,
+        }
+    }
 }
 
 /// A button on a controller.
@@ -24,7 +217,7 @@ struct Event {
 /// Example controller:
 ///
 /// <img src="https://libcala.github.io/stick/res/controller.png" width="292">
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Btn {
     /// D-PAD LEFT / LEFT ARROW KEY / SCROLL UP "Previous Item"
@@ -74,10 +267,14 @@ impl From<Btn> for u8 {
 #[derive(Debug)]
 pub struct Device {
     native_handle: u32,
+    info: DeviceInfo,
     // Hardware ID for this device.
     hardware_id: u32,
     abs_min: i32,
     abs_max: i32,
+    // Whether it has the kernel's gamepad buttons (`BTN_GAMEPAD`), rather
+    // than numbered joystick buttons.
+    gamepad: bool,
     // 256 bits total
 
     // AXIS (Atomic f32)
@@ -89,15 +286,170 @@ pub struct Device {
     trgr: AtomicU32,
     // BTNS (32 bits)
     btns: AtomicU32,
+    // Raw evdev state.
+    keys: [AtomicU32; KEY_CNT / 32],
+    abs: [AtomicU32; ABS_CNT],
+    // `AxisKind` of each `EV_ABS` code.
+    kinds: [AtomicU8; ABS_CNT],
+    // How far triggers are pulled to press and release `Btn::L`/`Btn::R`
+    // (Atomic f32).
+    press: AtomicU32,
+    release: AtomicU32,
+    calibration: RwLock<Calibration>,
+    // A mapping made with the `MappingWizard`, instead of the built-in one.
+    mapping: RwLock<Option<Mapping>>,
+    // `StickShape` of both sticks.
+    shape: AtomicU8,
+    // Is it a Joy-Con held sideways?
+    sideways: AtomicBool,
     // Is it plugged in?
     plug: AtomicBool,
+    // Sequence lock: odd while the input thread is writing.
+    seq: AtomicU32,
 }
 
-impl std::fmt::Display for Device {
+/// A copy of the state of a `Device`, taken all at once.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DeviceState {
+    pub(crate) hardware_id: u32,
+    pub(crate) joy: (f32, f32),
+    pub(crate) cam: (f32, f32),
+    pub(crate) lrt: (f32, f32),
+    pub(crate) btns: u32,
+}
+
+impl DeviceState {
+    /// Get main joystick state if a main joystick exists, otherwise return
+    /// `None`.
+    pub fn joy(&self) -> Option<(f32, f32)> {
+        Some(self.joy)
+    }
+
+    /// Get X & Y from camera stick if it exists, otherwise return `None`.
+    pub fn cam(&self) -> Option<(f32, f32)> {
+        #[allow(clippy::single_match)]
+        match self.hardware_id {
+            // Flight controller
+            0x_07B5_0316 => return None,
+            _ => {}
+        }
+
+        Some(self.cam)
+    }
+
+    /// Get the left & right trigger values.
+    pub fn lrt(&self) -> Option<(f32, f32)> {
+        Some(self.lrt)
+    }
+
+    /// Return `Some(true)` if a button is pressed, `Some(false)` if not, and
+    /// `None` if the button doesn't exist.
+    pub fn btn<B: Into<u8>>(&self, b: B) -> Option<bool> {
+        Some(self.btns & (1 << (b.into())) != 0)
+    }
+}
+
+// Number of `EV_KEY` codes (`KEY_CNT`) and `EV_ABS` codes (`ABS_CNT`).
+const KEY_CNT: usize = 0x300;
+const ABS_CNT: usize = 0x40;
+
+/// A copy of the raw evdev state of a `Device`, before it's mapped to
+/// buttons and axes.  This includes inputs that don't have a `Btn`, like
+/// paddles, touchpad clicks and extra buttons on flight sticks.
+#[derive(Copy, Clone, PartialEq)]
+pub struct RawState {
+    keys: [u32; KEY_CNT / 32],
+    abs: [i32; ABS_CNT],
+}
+
+impl Default for RawState {
+    fn default() -> Self {
+        RawState {
+            keys: [0; KEY_CNT / 32],
+            abs: [0; ABS_CNT],
+        }
+    }
+}
+
+impl std::fmt::Debug for RawState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawState")
+            .field("keys", &self.keys().collect::<Vec<_>>())
+            .field("abs", &&self.abs[..])
+            .finish()
+    }
+}
+
+impl RawState {
+    /// Check if an `EV_KEY` code (`BTN_*` or `KEY_*`) is held.
+    pub fn key(&self, code: u16) -> bool {
+        let code = code as usize;
+        code < KEY_CNT && self.keys[code / 32] & (1 << (code % 32)) != 0
+    }
+
+    /// Get the value of an `EV_ABS` code (`ABS_*`), with no normalization.
+    /// Its range is in `DeviceInfo::abs()`.
+    pub fn abs(&self, code: u8) -> i32 {
+        self.abs.get(code as usize).cloned().unwrap_or(0)
+    }
+
+    /// Get every `EV_KEY` code that's held.
+    pub fn keys(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..KEY_CNT as u16).filter(move |code| self.key(*code))
+    }
+
+    // Apply a raw evdev event.
+    fn apply(&mut self, ev: &EvdevEvent) {
+        let code = ev.ev_code as u16 as usize;
+        match ev.ev_type {
+            0x01 if code < KEY_CNT => {
+                let bit = 1 << (code % 32);
+                if ev.ev_value != 0 {
+                    self.keys[code / 32] |= bit;
+                } else {
+                    self.keys[code / 32] &= !bit;
+                }
+            }
+            0x03 if code < ABS_CNT => self.abs[code] = ev.ev_value,
+            _ => {}
+        }
+    }
+}
+
+/// A cloneable handle to a `Device`, for reading its state from other threads
+/// while one thread drives `Port::input()`.
+///
+/// The handle stays attached to the physical device, so it keeps working
+/// after `Port::swap()`.  Once the device is unplugged, `state()` returns
+/// `None`.
+#[derive(Debug, Clone)]
+pub struct DeviceHandle(Arc<Device>);
+
+impl DeviceHandle {
+    /// Get a consistent copy of the device's state, or `None` if it's been
+    /// unplugged.
+    pub fn state(&self) -> Option<DeviceState> {
+        if self.0.plug.load(Ordering::Acquire) {
+            Some(self.0.snapshot())
+        } else {
+            None
+        }
+    }
+}
+
+impl std::ops::Deref for DeviceHandle {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for DeviceState {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        let joy: (f32, f32) = (gfloat(&self.joyx), gfloat(&self.joyy));
-        let cam: (f32, f32) = (gfloat(&self.camx), gfloat(&self.camy));
-        let lrt: (f32, f32) = (gfloat(&self.trgl), gfloat(&self.trgr));
+        let joy: (f32, f32) = self.joy;
+        let cam: (f32, f32) = self.cam;
+        let lrt: (f32, f32) = self.lrt;
 
         fn button_symbol(state: Option<bool>) -> char {
             if state == Some(true) {
@@ -157,228 +509,1292 @@ impl std::fmt::Display for Device {
     }
 }
 
-impl Device {
-    /// Get main joystick state from the device if a main joystick exists, otherwise return `None`.
-    pub fn joy(&self) -> Option<(f32, f32)> {
-        Some((gfloat(&self.joyx), gfloat(&self.joyy)))
+impl std::fmt::Display for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        self.snapshot().fmt(f)
     }
+}
 
-    /// Get X & Y from camera stick if it exists, otherwise return `None`.
-    pub fn cam(&self) -> Option<(f32, f32)> {
-        #[allow(clippy::single_match)]
-        match self.hardware_id {
-            // Flight controller
-            0x_07B5_0316 => return None,
-            _ => {}
-        }
+impl Device {
+    fn new(native_handle: u32, info: DeviceInfo) -> Self {
+        // The main stick's range, or the camera stick's if there isn't one.
+        let abs = info.abs(0).or_else(|| info.abs(3)).unwrap_or_default();
+        // Axes start where they were when the device was opened.
+        let raw_abs = std::array::from_fn(|i| {
+            let value = info.abs(i as u8).map(|abs| abs.value);
+            AtomicU32::new(value.unwrap_or(0) as u32)
+        });
+        let kinds = std::array::from_fn(|i| {
+            AtomicU8::new(info.axis_kind(i as u8) as u8)
+        });
+        let shape = AtomicU8::new(info.stick_shape() as u8);
+        let gamepad = info.keys.iter().any(|k| (0x130..=0x13E).contains(k));
+
+        Device {
+            native_handle,
+            hardware_id: info.hardware_id,
+            abs_min: abs.min,
+            abs_max: abs.max,
+            gamepad,
+            info,
 
-        Some((gfloat(&self.camx), gfloat(&self.camy)))
+            joyx: AtomicU32::new(0),
+            joyy: AtomicU32::new(0),
+            camx: AtomicU32::new(0),
+            camy: AtomicU32::new(0),
+            trgl: AtomicU32::new(0),
+            trgr: AtomicU32::new(0),
+            btns: AtomicU32::new(0),
+            keys: std::array::from_fn(|_| AtomicU32::new(0)),
+            abs: raw_abs,
+            kinds,
+            press: AtomicU32::new(0.99f32.to_bits()),
+            release: AtomicU32::new(0.99f32.to_bits()),
+            calibration: RwLock::new(Calibration::new()),
+            mapping: RwLock::new(None),
+            shape,
+            sideways: AtomicBool::new(false),
+            plug: AtomicBool::new(false),
+            seq: AtomicU32::new(0),
+        }
     }
 
-    /// Get the left & right trigger values.
-    pub fn lrt(&self) -> Option<(f32, f32)> {
-        Some((gfloat(&self.trgl), gfloat(&self.trgr)))
+    /// Get a copy of the whole device state at once.  Every value comes from
+    /// the same completed evdev frame (ended by `SYN_REPORT`).
+    pub fn snapshot(&self) -> DeviceState {
+        self.read(|| DeviceState {
+            hardware_id: self.hardware_id,
+            joy: (gfloat(&self.joyx), gfloat(&self.joyy)),
+            cam: (gfloat(&self.camx), gfloat(&self.camy)),
+            lrt: (gfloat(&self.trgl), gfloat(&self.trgr)),
+            btns: self.btns.load(Ordering::Relaxed),
+        })
     }
 
-    /// Return `Some(true)` if a button is pressed, `Some(false)` if not, and `None` if the button
-    /// doesn't exist.
-    pub fn btn<B: Into<u8>>(&self, b: B) -> Option<bool> {
-        Some(self.btns.load(Ordering::Relaxed) & (1 << (b.into())) != 0)
+    /// Get a copy of the raw evdev state at once, from the same frame as
+    /// `snapshot()`.
+    pub fn raw(&self) -> RawState {
+        self.read(|| {
+            let mut raw = RawState::default();
+            for (key, atomic) in raw.keys.iter_mut().zip(self.keys.iter()) {
+                *key = atomic.load(Ordering::Relaxed);
+            }
+            for (abs, atomic) in raw.abs.iter_mut().zip(self.abs.iter()) {
+                *abs = atomic.load(Ordering::Relaxed) as i32;
+            }
+            raw
+        })
     }
-}
 
-// Adjust atomic float.
-fn afloat(float: &AtomicU32, fnc: &dyn Fn(f32) -> f32) {
-    let old = f32::from_bits(float.load(Ordering::Relaxed));
+    /// Get the range and current raw value of an `EV_ABS` code (`ABS_*`), if
+    /// the device has it.
+    pub fn abs(&self, code: u8) -> Option<AbsInfo> {
+        let mut abs = self.info.abs(code)?;
+        abs.value = self.raw().abs(code);
+        Some(abs)
+    }
 
-    float.store(fnc(old).to_bits(), Ordering::Relaxed);
-}
+    /// Get any absolute axis (`ABS_*` code) across its range, such as a
+    /// throttle, rudder or slider on a flight stick: -1.0 to 1.0 if it's
+    /// `Bipolar`, otherwise 0.0 to 1.0.  Returns `None` if the device doesn't
+    /// have it.
+    pub fn axis(&self, code: u8) -> Option<f32> {
+        self.scale_axis(code, self.raw().abs(code))
+    }
 
-// Get atomic float.
-fn gfloat(float: &AtomicU32) -> f32 {
-    f32::from_bits(float.load(Ordering::Relaxed))
-}
+    /// Get the device's calibration (see `Port::set_calibration()`).
+    pub fn calibration(&self) -> Calibration {
+        self.calibration.read().unwrap().clone()
+    }
 
-/// An interface to all joystick, gamepad and controller devices.
-pub struct Port {
-    // Native bindings to file descriptors
-    manager: NativeManager,
-    // Number of controllers.
-    count: AtomicUsize,
-    // The controllers' data.
-    controllers: [Device; CONTROLLER_MAX],
-}
+    /// Get the device's mapping, if it has one instead of the built-in
+    /// mapping (see `Port::set_mapping()`).
+    pub fn mapping(&self) -> Option<Mapping> {
+        self.mapping.read().unwrap().clone()
+    }
 
-impl Default for Port {
-    fn default() -> Self {
-        Self::new()
+    // Use a mapping instead of the built-in one, with its stick shape.
+    fn set_mapping(&self, mapping: Option<Mapping>) {
+        let shape = mapping.as_ref().and_then(|mapping| mapping.stick_shape());
+        let shape = shape.unwrap_or_else(|| self.info.stick_shape());
+        self.shape.store(shape as u8, Ordering::Relaxed);
+        *self.mapping.write().unwrap() = mapping;
     }
-}
 
-impl Port {
-    /// Create a new interface to all joystick, gamepad and controller devices currently plugged in
-    /// to this computer.
-    pub fn new() -> Port {
-        let manager = NativeManager::new();
-        let controllers = unsafe { std::mem::zeroed() };
-        let count = AtomicUsize::new(0);
+    // Get the calibrated range of an axis.
+    fn calibrated(&self, code: u8) -> Option<AxisRange> {
+        self.calibration.read().unwrap().axis(code)
+    }
 
-        let mut port = Port {
-            manager,
-            count,
-            controllers,
+    // Scale a raw value of an axis without a deadzone, using its calibration
+    // if it has one.
+    fn scale_axis(&self, code: u8, value: i32) -> Option<f32> {
+        let abs = self.info.abs(code)?;
+        let kind = self.axis_kind(code);
+        let range = if let Some(range) = self.calibrated(code) {
+            range
+        } else {
+            return Some(axis_value(kind, &abs, value));
         };
+        Some(match kind {
+            AxisKind::Bipolar => {
+                calibrate::scale(&range, value).clamp(-1.0, 1.0)
+            }
+            AxisKind::Unipolar => unipolar(range.min, range.max, value),
+            AxisKind::Inverted => 1.0 - unipolar(range.min, range.max, value),
+        })
+    }
 
-        for stick in 0..port.manager.num_plugged_in() {
-            port.add_stick(stick);
-        }
+    /// Get how an absolute axis (`ABS_*` code) moves.  See
+    /// `DeviceInfo::axis_kind()` and `Port::set_axis_kind()`.
+    pub fn axis_kind(&self, code: u8) -> AxisKind {
+        self.kinds
+            .get(code as usize)
+            .map(|kind| AxisKind::from_u8(kind.load(Ordering::Relaxed)))
+            .unwrap_or(AxisKind::Bipolar)
+    }
 
-        port
+    /// Get how the device's sticks are mapped to a circle.  See
+    /// `DeviceInfo::stick_shape()` and `Port::set_stick_shape()`.
+    pub fn stick_shape(&self) -> StickShape {
+        StickShape::from_u8(self.shape.load(Ordering::Relaxed))
     }
 
-    fn add_stick(&mut self, index: usize) {
-        let (min, max, _) = self.manager.get_abs(index);
+    // Map the sticks of a frame from the shape of their gates.
+    fn shape(&self, state: &DeviceState) -> DeviceState {
+        let shape = self.stick_shape();
+        let joy = Stick::from(state.joy).shaped(shape);
+        let cam = Stick::from(state.cam).shaped(shape);
+        DeviceState {
+            joy: (joy.x, joy.y),
+            cam: (cam.x, cam.y),
+            ..*state
+        }
+    }
 
-        self.count.fetch_add(1, Ordering::Relaxed);
+    /// Check if a Joy-Con is held sideways, as a mini gamepad.  See
+    /// `Port::set_sideways()`.
+    pub fn sideways(&self) -> bool {
+        self.sideways.load(Ordering::Relaxed)
+    }
 
-        self.controllers[index] = Device {
-            native_handle: index as u32,
-            hardware_id: self.manager.get_id(index).0,
-            abs_min: min,
-            abs_max: max,
+    /// Get the direction a hat (0 to 3) is pushed, or `None` if it's
+    /// centered or the device doesn't have it.
+    pub fn hat(&self, hat: u8) -> Option<Direction> {
+        let raw = self.raw();
+        hat_direction(&raw, hat)
+    }
 
-            joyx: AtomicU32::new(0),
-            joyy: AtomicU32::new(0),
-            camx: AtomicU32::new(0),
-            camy: AtomicU32::new(0),
-            trgl: AtomicU32::new(0),
-            trgr: AtomicU32::new(0),
-            btns: AtomicU32::new(0),
-            plug: AtomicBool::new(true),
-        };
+    /// Check if any button (`BTN_*` or `KEY_*` code) is held, including ones
+    /// without a `Btn`.
+    pub fn button(&self, code: u16) -> bool {
+        self.raw().key(code)
     }
 
-    /// Block thread until input is available.
-    pub async fn input(&mut self) -> Option<u8> {
-        if let Some(fd) = self.manager.async_device {
-            if fd == self.manager.async_device.fd() {
-                // not a joystick (one's been plugged in).
-                let (is_add, index) =
-                    crate::ffi::inotify_read(&mut self.manager)?;
-                println!("Controller Count Changed {} {}", is_add, index);
-
-                if is_add {
-                    // FOR TESTING
-                    // println!("s{:08X}", self.manager.get_id(added).0);
-                    self.add_stick(index);
-                    return Some(index as u8);
-                } else {
-                    return None;
-                }
+    // Read consistently with the sequence lock.
+    fn read<T>(&self, read: impl Fn() -> T) -> T {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 != 0 {
+                std::hint::spin_loop();
+                continue;
             }
 
-            for i in 0..self.controllers.len() {
-                let (devfd, is_out, ne) = self
-                    .manager
-                    .get_fd(self.controllers[i].native_handle as usize);
+            let value = read();
 
-                if ne {
-                    continue;
-                }
+            atomic::fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return value;
+            }
+        }
+    }
 
-                if is_out {
-                    self.count.fetch_sub(1, Ordering::Relaxed);
-                    self.manager.disconnect(fd);
-                    continue;
-                }
+    // Publish a completed frame to readers (only called from the input
+    // thread).
+    fn publish(&self, state: &DeviceState, raw: &RawState) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+
+        sfloat(&self.joyx, state.joy.0);
+        sfloat(&self.joyy, state.joy.1);
+        sfloat(&self.camx, state.cam.0);
+        sfloat(&self.camy, state.cam.1);
+        sfloat(&self.trgl, state.lrt.0);
+        sfloat(&self.trgr, state.lrt.1);
+        self.btns.store(state.btns, Ordering::Relaxed);
+        for (atomic, key) in self.keys.iter().zip(raw.keys.iter()) {
+            atomic.store(*key, Ordering::Relaxed);
+        }
+        for (atomic, abs) in self.abs.iter().zip(raw.abs.iter()) {
+            atomic.store(*abs as u32, Ordering::Relaxed);
+        }
 
-                if devfd != fd {
-                    continue;
-                }
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
 
-                while joystick_poll_event(fd, &mut self.controllers[i]) {}
+    /// Get the device's hardware ID, name and axis ranges.
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
 
-                return Some(i as u8);
-            }
+    /// Get main joystick state from the device if a main joystick exists, otherwise return `None`.
+    pub fn joy(&self) -> Option<(f32, f32)> {
+        self.snapshot().joy()
+    }
+
+    /// Get X & Y from camera stick if it exists, otherwise return `None`.
+    pub fn cam(&self) -> Option<(f32, f32)> {
+        self.snapshot().cam()
+    }
+
+    /// Get the left & right trigger values.
+    pub fn lrt(&self) -> Option<(f32, f32)> {
+        self.snapshot().lrt()
+    }
+
+    /// Return `Some(true)` if a button is pressed, `Some(false)` if not, and `None` if the button
+    /// doesn't exist.
+    pub fn btn<B: Into<u8>>(&self, b: B) -> Option<bool> {
+        self.snapshot().btn(b)
+    }
+}
+
+// Set atomic float.
+fn sfloat(float: &AtomicU32, value: f32) {
+    float.store(value.to_bits(), Ordering::Relaxed);
+}
+
+// Get atomic float.
+fn gfloat(float: &AtomicU32) -> f32 {
+    f32::from_bits(float.load(Ordering::Relaxed))
+}
+
+// Queue raw events for everything that changed between two frames.
+fn diff_raw(
+    id: u8,
+    device: &Device,
+    old: &RawState,
+    new: &RawState,
+    events: &mut VecDeque<(u8, Event)>,
+) {
+    for (i, (old, new)) in old.keys.iter().zip(new.keys.iter()).enumerate() {
+        let changed = old ^ new;
+        for bit in (0..32).filter(|bit| changed & (1 << bit) != 0) {
+            let pressed = new & (1 << bit) != 0;
+            events
+                .push_back((id, Event::Button((i * 32 + bit) as u16, pressed)));
         }
-        None
     }
 
-    /// Get the state of a device
-    pub fn get(&self, stick: u8) -> Option<&Device> {
-        if self.controllers[stick as usize]
-            .plug
-            .load(Ordering::Relaxed)
+    for (code, _) in device.info.abs.iter() {
+        let code = *code;
+        if (ABS_HAT0X..=ABS_HAT3Y).contains(&code)
+            || old.abs(code) == new.abs(code)
         {
-            Some(&self.controllers[stick as usize])
-        } else {
-            None
+            continue;
+        }
+        if let Some(value) = device.scale_axis(code, new.abs(code)) {
+            events.push_back((id, Event::Axis(code, value)));
         }
     }
 
-    /// Swap two devices in the interface by their indexes.
-    /// # Panics
-    /// If either `a` or `b` are out of bounds.
-    /// # Note
-    /// This is useful for if in a game, you want P1 and P2 to swap which controller they are
-    /// assigned to.  You can do this with:
-    /// ```norun
-    /// // Assuming P1 is at index 0, and P2 is at index 1,
-    /// devices.swap(0, 1);
-    /// ```
-    pub fn swap(&mut self, a: u8, b: u8) {
-        self.controllers.swap(a as usize, b as usize);
+    for hat in 0..4 {
+        let dir = hat_direction(new, hat);
+        if hat_direction(old, hat) != dir {
+            events.push_back((id, Event::Hat(hat, dir)));
+        }
     }
+}
 
-    /// Get the name of a device by index.
-    #[allow(unused)]
-    pub fn name(&self, a: u8) -> String {
-        // TODO
-        "Unknown".to_string()
+// Scale a raw axis value from its range, without a deadzone.
+fn axis_value(kind: AxisKind, abs: &AbsInfo, value: i32) -> f32 {
+    let value = unipolar(abs.min, abs.max, value);
+    match kind {
+        AxisKind::Bipolar if abs.max > abs.min => value * 2.0 - 1.0,
+        AxisKind::Bipolar => 0.0,
+        AxisKind::Unipolar => value,
+        AxisKind::Inverted => 1.0 - value,
     }
+}
 
-    /// Get the number of plugged in controllers.
-    pub fn count(&self) -> u8 {
-        self.count.load(Ordering::Relaxed) as u8
+// Get the direction of a hat from its X and Y axes.
+fn hat_direction(raw: &RawState, hat: u8) -> Option<Direction> {
+    if hat >= 4 {
+        return None;
     }
+    let x = raw.abs(ABS_HAT0X + hat * 2).signum() as f32;
+    let y = raw.abs(ABS_HAT0X + hat * 2 + 1).signum() as f32;
+    Stick { x, y }.direction8(0.5)
+}
+
+// A left and right Joy-Con paired into one device: the native handle and
+// info of each.
+#[derive(Clone)]
+struct Pair {
+    left: (usize, DeviceInfo),
+    right: (usize, DeviceInfo),
+}
+
+// The controllers of a `Port`, fed by raw input from its backend.
+pub(crate) struct Controllers {
+    // Number of controllers.
+    count: usize,
+    // The controllers' data, shared with `DeviceHandle`s.
+    devices: Vec<Arc<Device>>,
+    // The frame being built for each controller, published on `SYN_REPORT`.
+    pending: Vec<DeviceState>,
+    pending_raw: Vec<RawState>,
+    // The last complete frame of each controller, before its sticks are
+    // shaped, to go back to when input is dropped.
+    reported: Vec<DeviceState>,
+    // Whether each controller's events are thrown away until the next
+    // `SYN_REPORT`, after a `SYN_DROPPED`.
+    dropping: Vec<bool>,
+    // Events that haven't been returned yet.
+    pub(crate) events: VecDeque<(u8, Event)>,
+    // Gestures to recognize, and each controller's progress on them.
+    gestures: Vec<Gesture>,
+    recognizers: Vec<Recognizer>,
+    // Whether to send events for every raw button and axis.
+    raw_events: bool,
+    // The Joy-Cons paired into each slot's device.
+    pairs: Vec<Option<Pair>>,
+    // Whether to pair and turn Joy-Cons by holding their buttons.
+    joycon_pairing: bool,
+    // Where calibration profiles are loaded from.
+    calibration_dir: Option<PathBuf>,
+    // Where mappings are loaded from.
+    mapping_dir: Option<PathBuf>,
+    // Which devices to take, if not all of them.
+    filter: Option<Filter>,
 }
 
-fn joystick_poll_event(fd: i32, device: &mut Device) -> bool {
-    extern "C" {
-        fn read(fd: i32, buf: *mut Event, count: usize) -> isize;
+impl Controllers {
+    pub(crate) fn new() -> Self {
+        let devices = (0..CONTROLLER_MAX)
+            .map(|i| Arc::new(Device::new(i as u32, DeviceInfo::default())))
+            .collect();
+        let pending = vec![DeviceState::default(); CONTROLLER_MAX];
+        let recognizers =
+            (0..CONTROLLER_MAX).map(|_| Recognizer::default()).collect();
+
+        Controllers {
+            count: 0,
+            devices,
+            pending,
+            pending_raw: vec![RawState::default(); CONTROLLER_MAX],
+            reported: vec![DeviceState::default(); CONTROLLER_MAX],
+            dropping: vec![false; CONTROLLER_MAX],
+            events: VecDeque::new(),
+            gestures: Vec::new(),
+            recognizers,
+            raw_events: false,
+            pairs: vec![None; CONTROLLER_MAX],
+            joycon_pairing: false,
+            calibration_dir: None,
+            mapping_dir: None,
+            filter: None,
+        }
     }
 
-    let mut js = std::mem::MaybeUninit::uninit();
-    let bytes = unsafe { read(fd, js.as_mut_ptr(), std::mem::size_of::<Event>()) };
-    if bytes != (std::mem::size_of::<Event>() as isize) {
-        return false;
+    // Find the index of a plugged in controller by its native handle.
+    fn slot(&self, native: usize) -> Option<usize> {
+        (0..self.devices.len()).find(|&slot| {
+            let device = &self.devices[slot];
+            let paired = &self.pairs[slot];
+            device.plug.load(Ordering::Relaxed)
+                && (device.native_handle as usize == native
+                    || paired.as_ref().is_some_and(|p| p.right.0 == native))
+        })
     }
-    let js = unsafe { js.assume_init() };
 
-    fn edit<B: Into<u8>>(is: bool, device: &mut Device, b: B) {
-        if is {
-            device.btns.fetch_or(1 << b.into(), Ordering::Relaxed);
+    // Get the native handles of the device in a slot.
+    pub(crate) fn natives(&self, stick: u8) -> Vec<usize> {
+        let device = if let Some(device) = self.get(stick) {
+            device
         } else {
-            device.btns.fetch_and(!(1 << b.into()), Ordering::Relaxed);
+            return Vec::new();
+        };
+        let mut natives = vec![device.native_handle as usize];
+        if let Some(pair) = &self.pairs[stick as usize] {
+            natives.push(pair.right.0);
         }
+        natives
     }
 
-    // Apply Mods
-    let a = if device.hardware_id == 0x_0E6F_0501
-    /* XBOX */
-    {
-        Btn::B
-    } else {
-        Btn::A
-    };
-
-    let b = if device.hardware_id == 0x_0E6F_0501
-    /* XBOX */
-    {
-        Btn::A
-    } else {
-        Btn::B
-    };
+    // Put a newly plugged in controller in the first free slot.
+    fn connect(&mut self, native: usize, info: DeviceInfo) -> Option<usize> {
+        let slot = self
+            .devices
+            .iter()
+            .position(|d| !d.plug.load(Ordering::Relaxed))?;
+
+        let device = Device::new(native as u32, info);
+        if let Some(dir) = &self.calibration_dir {
+            // A profile that can't be read is the same as none.
+            let profile = Calibration::load_profile(dir, device.info());
+            if let Ok(Some(calibration)) = profile {
+                *device.calibration.write().unwrap() = calibration;
+            }
+        }
+        if let Some(dir) = &self.mapping_dir {
+            // Same for mappings.
+            let mapping = Mapping::load_profile(dir, device.info());
+            if let Ok(Some(mapping)) = mapping {
+                device.set_mapping(Some(mapping));
+            }
+        }
+        device.plug.store(true, Ordering::Release);
+
+        self.count += 1;
+        self.pending[slot] = device.snapshot();
+        self.pending_raw[slot] = device.raw();
+        self.reported[slot] = self.pending[slot];
+        self.dropping[slot] = false;
+        self.devices[slot] = Arc::new(device);
+        self.recognizers[slot].reset();
+        self.events.push_back((slot as u8, Event::Connect));
+        Some(slot)
+    }
+
+    fn disconnect(&mut self, native: usize) {
+        if let Some(slot) = self.slot(native) {
+            self.unplug(slot);
+            // The other Joy-Con of a pair carries on by itself.
+            if let Some(pair) = self.pairs[slot].take() {
+                let (native, info) = if pair.left.0 == native {
+                    pair.right
+                } else {
+                    pair.left
+                };
+                self.connect(native, info);
+            }
+        }
+    }
+
+    // Remove the device in a slot.
+    fn unplug(&mut self, slot: usize) {
+        self.count -= 1;
+        // Handles to this device now see it as unplugged.
+        self.devices[slot].plug.store(false, Ordering::Release);
+        self.events.push_back((slot as u8, Event::Disconnect));
+    }
+
+    // Merge a left and right Joy-Con into one device.
+    pub(crate) fn pair(&mut self, a: u8, b: u8) -> bool {
+        let (a, b) = (a as usize, b as usize);
+        let single = |slot: usize| {
+            self.get(slot as u8).is_some() && self.pairs[slot].is_none()
+        };
+        if a == b || !single(a) || !single(b) {
+            return false;
+        }
+        let (left, right) =
+            match (self.devices[a].hardware_id, self.devices[b].hardware_id) {
+                (joycon::JOYCON_L, joycon::JOYCON_R) => (a, b),
+                (joycon::JOYCON_R, joycon::JOYCON_L) => (b, a),
+                _ => return false,
+            };
+
+        let side = |slot: usize| {
+            let device = &self.devices[slot];
+            (device.native_handle as usize, device.info.clone())
+        };
+        let pair = Pair {
+            left: side(left),
+            right: side(right),
+        };
+        self.unplug(a);
+        self.unplug(b);
+        let info = joycon::pair(&pair.left.1, &pair.right.1);
+        if let Some(slot) = self.connect(pair.left.0, info) {
+            self.pairs[slot] = Some(pair);
+        }
+        true
+    }
+
+    // Split paired Joy-Cons back into two devices.
+    pub(crate) fn unpair(&mut self, stick: u8) {
+        let slot = stick as usize;
+        if self.get(stick).is_none() {
+            return;
+        }
+        if let Some(pair) = self.pairs[slot].take() {
+            self.unplug(slot);
+            self.connect(pair.left.0, pair.left.1);
+            self.connect(pair.right.0, pair.right.1);
+        }
+    }
+
+    // In pairing mode, pair Joy-Cons that hold L and R at the same time, and
+    // turn ones that hold SL and SR sideways.
+    fn pairing(&mut self, slot: usize, old: &RawState) {
+        let device = &self.devices[slot];
+        let hardware_id = device.hardware_id;
+        let (rail, shoulder) =
+            match (joycon::rail(hardware_id), joycon::shoulder(hardware_id)) {
+                (Some(rail), Some(shoulder)) => (rail, shoulder),
+                _ => return,
+            };
+        let new = device.raw();
+        let pressed = |codes: &[u16]| {
+            codes.iter().all(|c| new.key(*c))
+                && !codes.iter().all(|c| old.key(*c))
+        };
+
+        if pressed(&[rail.0, rail.1]) {
+            device.sideways.fetch_xor(true, Ordering::Relaxed);
+        } else if pressed(&[shoulder]) {
+            let other = (0..CONTROLLER_MAX).find(|&other| {
+                let device = &self.devices[other];
+                let id = device.hardware_id;
+                other != slot
+                    && self.pairs[other].is_none()
+                    && device.plug.load(Ordering::Relaxed)
+                    && id != hardware_id
+                    && joycon::shoulder(id).is_some_and(|s| device.raw().key(s))
+            });
+            if let Some(other) = other {
+                self.pair(slot as u8, other as u8);
+            }
+        }
+    }
+
+    pub(crate) fn apply(&mut self, input: RawInput) {
+        match input {
+            RawInput::Connect(native, info) => {
+                if self.filter.as_ref().is_none_or(|f| f(&info)) {
+                    self.connect(native, info);
+                }
+            }
+            RawInput::Disconnect(native) => self.disconnect(native),
+            RawInput::Event(native, ev_type, ev_code, ev_value) => {
+                let ev = EvdevEvent {
+                    ev_type: ev_type as i16,
+                    ev_code: ev_code as i16,
+                    ev_value,
+                };
+                self.event(native, &ev);
+            }
+        }
+    }
+
+    // Run a raw evdev event through the mapping.
+    fn event(&mut self, native: usize, ev: &EvdevEvent) {
+        let slot = if let Some(slot) = self.slot(native) {
+            slot
+        } else {
+            return;
+        };
+
+        let device = &self.devices[slot];
+        if let Some(pair) = &self.pairs[slot] {
+            // Paired Joy-Cons don't have SL and SR, which would otherwise
+            // be the other side's L and R.
+            let side = if pair.left.0 == native {
+                joycon::JOYCON_L
+            } else {
+                joycon::JOYCON_R
+            };
+            let code = ev.ev_code as u16;
+            if ev.ev_type == 0x01
+                && joycon::rail(side)
+                    .is_some_and(|r| code == r.0 || code == r.1)
+            {
+                return;
+            }
+        }
+        if ev.ev_type == 0x00 && ev.ev_code == 3 {
+            // SYN_DROPPED: the kernel buffer overran, so throw away the
+            // partial frame, and the rest of it up to the next SYN_REPORT.
+            self.pending[slot] = self.reported[slot];
+            self.pending_raw[slot] = device.raw();
+            self.dropping[slot] = true;
+            return;
+        }
+        if self.dropping[slot] {
+            self.dropping[slot] = ev.ev_type != 0x00 || ev.ev_code != 0;
+            return;
+        }
+        let state = &mut self.pending[slot];
+        let raw = &mut self.pending_raw[slot];
+        raw.apply(ev);
+        let mapped = if device.sideways() {
+            joycon::sideways(&device.info, ev)
+        } else {
+            Some(*ev)
+        };
+        let done =
+            mapped.is_some_and(|ev| joystick_map_event(&ev, device, state));
+        if done {
+            // The pending frame keeps the sticks as they're reported, since
+            // the next frame may only change one axis.
+            self.reported[slot] = *state;
+            let state = &device.shape(state);
+            let old = device.snapshot();
+            let old_raw = device.raw();
+            device.publish(state, raw);
+            diff(slot as u8, &old, state, &mut self.events);
+            diff_extra(slot as u8, device, &old_raw, raw, &mut self.events);
+            if self.raw_events {
+                let events = &mut self.events;
+                diff_raw(slot as u8, device, &old_raw, raw, events);
+            }
+
+            let now = Instant::now();
+            let recognizer = &mut self.recognizers[slot];
+            let mut found = Vec::new();
+            recognizer.tick(&self.gestures, now, &mut found);
+            recognizer.update(&self.gestures, now, state, &mut found);
+            for id in found {
+                self.events.push_back((slot as u8, Event::Gesture(id)));
+            }
+
+            if self.joycon_pairing && self.pairs[slot].is_none() {
+                self.pairing(slot, &old_raw);
+            }
+        }
+    }
+
+    // Recognize gestures that finish with time passing, rather than input.
+    pub(crate) fn tick(&mut self) {
+        let now = Instant::now();
+        for slot in 0..CONTROLLER_MAX {
+            if !self.devices[slot].plug.load(Ordering::Relaxed) {
+                continue;
+            }
+            let mut found = Vec::new();
+            self.recognizers[slot].tick(&self.gestures, now, &mut found);
+            for id in found {
+                self.events.push_back((slot as u8, Event::Gesture(id)));
+            }
+        }
+    }
+
+    // When `tick()` needs to be called next, if ever.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        (0..CONTROLLER_MAX)
+            .filter(|slot| self.devices[*slot].plug.load(Ordering::Relaxed))
+            .filter_map(|slot| self.recognizers[slot].deadline(&self.gestures))
+            .min()
+    }
+
+    // Plugged in devices, with their native handles.
+    pub(crate) fn plugged(&self) -> impl Iterator<Item = &Device> {
+        self.devices
+            .iter()
+            .map(|d| &**d)
+            .filter(|d| d.plug.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn get(&self, stick: u8) -> Option<&Device> {
+        let device = &self.devices[stick as usize];
+
+        if device.plug.load(Ordering::Relaxed) {
+            Some(device)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn handle(&self, stick: u8) -> Option<DeviceHandle> {
+        let device = &self.devices[stick as usize];
+
+        if device.plug.load(Ordering::Relaxed) {
+            Some(DeviceHandle(device.clone()))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn count(&self) -> u8 {
+        self.count as u8
+    }
+}
+
+/// An interface to all joystick, gamepad and controller devices.
+pub struct Port {
+    // Where raw input comes from.
+    backend: Box<dyn Backend>,
+    // The controllers' data.
+    controllers: Controllers,
+    // Where raw input is being recorded to.
+    recorder: Option<Recorder>,
+    // Wakes the task polling the `Stream` when a gesture is due.
+    timer: Timer,
+}
+
+impl Default for Port {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Port {
+    /// Create a new interface to all joystick, gamepad and controller devices currently plugged in
+    /// to this computer.  An `Event::Connect` is sent for each of them.
+    pub fn new() -> Port {
+        Self::builder().build()
+    }
+
+    /// Create a new interface to just the devices at `paths` (like
+    /// `/dev/input/event3` or a `/dev/input/by-id/` link), without watching
+    /// for others to be plugged in.  Devices that are unplugged don't come
+    /// back.  For fixed hardware, and for devices made with uinput.
+    #[cfg(all(not(target_os = "macos"), unix))]
+    pub fn with_paths<P: AsRef<std::path::Path>>(
+        paths: &[P],
+    ) -> io::Result<Port> {
+        let backend = NativeManager::with_paths(paths)?;
+        Ok(Self::builder().native_backend(backend).build())
+    }
+
+    /// Create a new interface to the devices of a backend other than the
+    /// native one, such as a [`MockBackend`](struct.MockBackend.html) or a
+    /// [`Replay`](struct.Replay.html).  It doesn't load calibration profiles
+    /// or mappings (see `PortBuilder::calibration_dir()`).
+    pub fn with_backend<B: Backend + 'static>(backend: B) -> Port {
+        Self::builder().backend(backend).build()
+    }
+
+    /// Start building a `Port` that only takes some devices.
+    pub fn builder() -> PortBuilder {
+        PortBuilder::new()
+    }
+
+    pub(crate) fn build(
+        backend: Box<dyn Backend>,
+        filter: Option<Filter>,
+        calibration_dir: Option<PathBuf>,
+        mapping_dir: Option<PathBuf>,
+    ) -> Port {
+        let mut controllers = Controllers::new();
+        controllers.filter = filter;
+        controllers.calibration_dir = calibration_dir;
+        controllers.mapping_dir = mapping_dir;
+        let mut port = Port {
+            backend,
+            controllers,
+            recorder: None,
+            timer: Timer::new(),
+        };
+
+        // Pick up devices that are already plugged in.
+        port.process(Some(Duration::from_secs(0)));
+
+        port
+    }
+
+    // Handle input from the backend, waiting up to `timeout` for some, and
+    // queue the events.  Returns `false` if there wasn't any input.
+    fn process(&mut self, timeout: Option<Duration>) -> bool {
+        let mut input = Vec::new();
+        self.backend.poll(timeout, &mut input);
+
+        let any = !input.is_empty();
+        for raw in input {
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.record(&raw);
+            }
+            self.controllers.apply(raw);
+        }
+        any
+    }
+
+    /// Start recording raw input from all devices to `out`, replacing any
+    /// recording in progress.  Devices that are already plugged in are
+    /// recorded as being plugged in at the start.  Play it back with
+    /// [`Replay`](struct.Replay.html).
+    pub fn record<W: Write + Send + 'static>(
+        &mut self,
+        out: W,
+    ) -> io::Result<()> {
+        self.stop_recording()?;
+
+        let mut recorder = Recorder::new(Box::new(out))?;
+        for device in self.controllers.plugged() {
+            let native = device.native_handle as usize;
+            recorder.record(&RawInput::Connect(native, device.info.clone()));
+        }
+        self.recorder = Some(recorder);
+
+        Ok(())
+    }
+
+    /// Stop recording and flush the output.  Returns the first error that
+    /// happened while recording, if any.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the next event if there is one, without waiting.  Call this in a
+    /// loop each frame to drain all pending events:
+    /// ```norun
+    /// while let Some((id, event)) = port.try_poll() {
+    ///     // ...
+    /// }
+    /// ```
+    pub fn try_poll(&mut self) -> Option<(u8, Event)> {
+        while self.controllers.events.is_empty() {
+            if !self.process(Some(Duration::from_secs(0))) {
+                self.controllers.tick();
+                break;
+            }
+        }
+        self.controllers.events.pop_front()
+    }
+
+    /// Block the thread until there's an event, or until `timeout` passes
+    /// (`None` waits forever).  Returns `None` straight away once the backend
+    /// is finished, like at the end of a `Replay`.
+    pub fn poll_blocking(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Option<(u8, Event)> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            if let Some(event) = self.try_poll() {
+                return Some(event);
+            }
+
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return None;
+            }
+            if self.controllers.deadline().is_none() && self.backend.finished()
+            {
+                return None;
+            }
+            // Wake up in time for a held button to become a gesture.
+            let wait = deadline
+                .into_iter()
+                .chain(self.controllers.deadline())
+                .min()
+                .map(|deadline| deadline.saturating_duration_since(now));
+
+            self.process(wait);
+        }
+    }
+
+    /// Wait until input is available from any device, and return the
+    /// device's index along with what changed.  Returns `None` once the
+    /// backend is finished, like at the end of a `Replay`.
+    pub async fn input(&mut self) -> Option<(u8, Event)> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Get the state of a device
+    pub fn get(&self, stick: u8) -> Option<&Device> {
+        self.controllers.get(stick)
+    }
+
+    /// Get a handle to a device that can be sent to other threads.
+    pub fn handle(&self, stick: u8) -> Option<DeviceHandle> {
+        self.controllers.handle(stick)
+    }
+
+    /// Swap two devices in the interface by their indexes.
+    /// # Panics
+    /// If either `a` or `b` are out of bounds.
+    /// # Note
+    /// This is useful for if in a game, you want P1 and P2 to swap which controller they are
+    /// assigned to.  You can do this with:
+    /// ```norun
+    /// // Assuming P1 is at index 0, and P2 is at index 1,
+    /// devices.swap(0, 1);
+    /// ```
+    pub fn swap(&mut self, a: u8, b: u8) {
+        self.controllers.devices.swap(a as usize, b as usize);
+        self.controllers.pending.swap(a as usize, b as usize);
+        self.controllers.pending_raw.swap(a as usize, b as usize);
+        self.controllers.reported.swap(a as usize, b as usize);
+        self.controllers.dropping.swap(a as usize, b as usize);
+        self.controllers.recognizers.swap(a as usize, b as usize);
+        self.controllers.pairs.swap(a as usize, b as usize);
+    }
+
+    /// Start recognizing a gesture on every controller, returning the id
+    /// that `Event::Gesture` will have when it's performed:
+    /// ```norun
+    /// let quit = port.gesture(Gesture::Hold(Btn::F, Duration::from_secs(2)));
+    /// let dash = port.gesture(Gesture::Taps(Btn::Right, 2, Duration::from_millis(250)));
+    /// ```
+    pub fn gesture(&mut self, gesture: Gesture) -> u16 {
+        self.controllers.gestures.push(gesture);
+        (self.controllers.gestures.len() - 1) as u16
+    }
+
+    /// Get the name of a device by index.
+    pub fn name(&self, a: u8) -> String {
+        if let Some(device) = self.get(a) {
+            device.info.name.clone()
+        } else {
+            "Unknown".to_string()
+        }
+    }
+
+    /// Get the number of plugged in controllers.
+    pub fn count(&self) -> u8 {
+        self.controllers.count()
+    }
+
+    /// Set the strength of force feedback effects on a device, from 0.0 to
+    /// 1.0 (`FF_GAIN`).
+    pub fn set_gain(&mut self, stick: u8, gain: f32) -> io::Result<()> {
+        self.write_ff(stick, 0x60, gain)
+    }
+
+    /// Set how strongly a wheel pulls itself back to the center, from 0.0
+    /// (off) to 1.0 (`FF_AUTOCENTER`).
+    pub fn set_autocenter(
+        &mut self,
+        stick: u8,
+        strength: f32,
+    ) -> io::Result<()> {
+        self.write_ff(stick, 0x61, strength)
+    }
+
+    // Send an `EV_FF` setting, scaled to `0..0xFFFF`.
+    fn write_ff(&mut self, stick: u8, code: u16, value: f32) -> io::Result<()> {
+        let value = (value.clamp(0.0, 1.0) * 65535.0) as i32;
+        self.write(stick, 0x15, code, value)
+    }
+
+    // Send an evdev event to a device.
+    pub(crate) fn write(
+        &mut self,
+        stick: u8,
+        ev_type: u16,
+        code: u16,
+        value: i32,
+    ) -> io::Result<()> {
+        for native in self.natives(stick)? {
+            self.backend.write(native, ev_type, code, value)?;
+        }
+        Ok(())
+    }
+
+    // Get the native handles of a device (two for paired Joy-Cons).
+    fn natives(&self, stick: u8) -> io::Result<Vec<usize>> {
+        let natives = self.controllers.natives(stick);
+        if natives.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No device at that index",
+            ));
+        }
+        Ok(natives)
+    }
+
+    /// Rumble a device, with its strong (low frequency) and weak (high
+    /// frequency) motors from 0.0 to 1.0, for `duration`.  Both Joy-Cons of
+    /// a pair rumble.
+    pub fn rumble(
+        &mut self,
+        stick: u8,
+        strong: f32,
+        weak: f32,
+        duration: Duration,
+    ) -> io::Result<()> {
+        let strong = (strong.clamp(0.0, 1.0) * 65535.0) as u16;
+        let weak = (weak.clamp(0.0, 1.0) * 65535.0) as u16;
+        for native in self.natives(stick)? {
+            self.backend.rumble(native, strong, weak, duration)?;
+        }
+        Ok(())
+    }
+
+    /// Set the color of a device's light, such as the lightbar of a
+    /// DualShock 4 or DualSense.  On Linux this uses the kernel's multicolor
+    /// LED in sysfs, which usually needs a udev rule to be writable.
+    pub fn set_light(
+        &mut self,
+        stick: u8,
+        r: u8,
+        g: u8,
+        b: u8,
+    ) -> io::Result<()> {
+        for native in self.natives(stick)? {
+            self.backend.set_light(native, r, g, b)?;
+        }
+        Ok(())
+    }
+
+    /// Turn Joy-Con pairing mode on or off, for a screen where players pick
+    /// how they hold their Joy-Cons.  While it's on, holding L and R on a
+    /// left and right Joy-Con at the same time pairs them, and holding SL
+    /// and SR turns a Joy-Con sideways (or back).  Off by default.
+    pub fn joycon_pairing(&mut self, enable: bool) {
+        self.controllers.joycon_pairing = enable;
+    }
+
+    /// Merge a left and right Joy-Con into one `Device`, which goes in the
+    /// first free slot.  Returns `false` if they aren't a left and right
+    /// Joy-Con that aren't paired already.
+    pub fn pair_joycons(&mut self, a: u8, b: u8) -> bool {
+        self.controllers.pair(a, b)
+    }
+
+    /// Split paired Joy-Cons back into two `Device`s.
+    pub fn unpair_joycons(&mut self, stick: u8) {
+        self.controllers.unpair(stick);
+    }
+
+    /// Hold a Joy-Con sideways, as a mini gamepad: the stick turns with it,
+    /// the four buttons by the stick are A, B, X and Y by where they are,
+    /// and SL and SR are the shoulder buttons.
+    pub fn set_sideways(&mut self, stick: u8, sideways: bool) {
+        if let Some(device) = self.get(stick) {
+            device.sideways.store(sideways, Ordering::Relaxed);
+        }
+    }
+
+    /// Set how an absolute axis (`ABS_*` code) of a device is scaled,
+    /// instead of the `AxisKind` from `DeviceInfo::axis_kind()`, until it's
+    /// unplugged.
+    pub fn set_axis_kind(&mut self, stick: u8, code: u8, kind: AxisKind) {
+        let device = self.get(stick);
+        if let Some(atomic) = device.and_then(|d| d.kinds.get(code as usize)) {
+            atomic.store(kind as u8, Ordering::Relaxed);
+        }
+    }
+
+    /// Set how a device's sticks are mapped to a circle, instead of the
+    /// `StickShape` from `DeviceInfo::stick_shape()`, until it's unplugged.
+    pub fn set_stick_shape(&mut self, stick: u8, shape: StickShape) {
+        if let Some(device) = self.get(stick) {
+            device.shape.store(shape as u8, Ordering::Relaxed);
+        }
+    }
+
+    /// Set how far a device's triggers have to be pulled (0.0 to 1.0) to
+    /// press `Btn::L` and `Btn::R`, and how far back they have to come to
+    /// release them.  Both are 0.99 by default.
+    pub fn set_trigger_thresholds(
+        &mut self,
+        stick: u8,
+        press: f32,
+        release: f32,
+    ) {
+        if let Some(device) = self.get(stick) {
+            sfloat(&device.press, press);
+            sfloat(&device.release, release.min(press));
+        }
+    }
+
+    /// Also send `Event::Button`, `Event::Axis` and `Event::Hat` for every
+    /// raw button, axis and hat that changes, for devices with more than a
+    /// gamepad has (like flight sticks and throttles).  Off by default.
+    pub fn raw_events(&mut self, enable: bool) {
+        self.controllers.raw_events = enable;
+    }
+
+    /// Calibrate a device, until it's unplugged.  See `Calibrator`.
+    pub fn set_calibration(&mut self, stick: u8, calibration: Calibration) {
+        if let Some(device) = self.get(stick) {
+            *device.calibration.write().unwrap() = calibration;
+        }
+    }
+
+    /// Set the directory that calibration profiles are loaded from when
+    /// devices are plugged in, or `None` to not load them.  See
+    /// `PortBuilder::calibration_dir()`.
+    pub fn calibration_dir(&mut self, dir: Option<PathBuf>) {
+        self.controllers.calibration_dir = dir;
+    }
+
+    /// Map a device's controls with a `Mapping` (like one made with the
+    /// `MappingWizard`) instead of the built-in mapping, until it's
+    /// unplugged.  `None` goes back to the built-in mapping.  This also sets
+    /// the `StickShape`, to the mapping's or else the built-in one.
+    pub fn set_mapping(&mut self, stick: u8, mapping: Option<Mapping>) {
+        if let Some(device) = self.get(stick) {
+            device.set_mapping(mapping);
+        }
+    }
+
+    /// Set the directory that mappings are loaded from when devices are
+    /// plugged in, or `None` to not load them.  See
+    /// `PortBuilder::mapping_dir()`.
+    pub fn mapping_dir(&mut self, dir: Option<PathBuf>) {
+        self.controllers.mapping_dir = dir;
+    }
+}
+
+impl Stream for Port {
+    type Item = (u8, Event);
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(event) = this.controllers.events.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            while this.controllers.events.is_empty() {
+                if !this.process(Some(Duration::from_secs(0))) {
+                    break;
+                }
+            }
+            this.controllers.tick();
+            if let Some(event) = this.controllers.events.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            let deadline = this.controllers.deadline();
+            if deadline.is_none() && this.backend.finished() {
+                this.timer.cancel();
+                return Poll::Ready(None);
+            }
+            if !this.backend.wake_on_input(cx) {
+                // Wake up in time for a held button to become a gesture.
+                match deadline {
+                    Some(deadline) => this.timer.wake_at(deadline, cx.waker()),
+                    None => this.timer.cancel(),
+                }
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+// Constructors for `Event`s that carry a button or an axis value.
+pub(crate) type BtnEvent = fn(bool) -> Event;
+type AxisEvent = fn(i8) -> Event;
+
+// Buttons that have an `Event`.
+const BTN_EVENTS: [(Btn, BtnEvent); 14] = [
+    (Btn::A, Event::Accept),
+    (Btn::B, Event::Cancel),
+    (Btn::X, Event::Common),
+    (Btn::Y, Event::Action),
+    (Btn::Up, Event::Up),
+    (Btn::Down, Event::Down),
+    (Btn::Left, Event::Left),
+    (Btn::Right, Event::Right),
+    (Btn::F, Event::Back),
+    (Btn::E, Event::Forward),
+    (Btn::W, Event::L),
+    (Btn::Z, Event::R),
+    (Btn::D, Event::MotionButton),
+    (Btn::C, Event::CameraButton),
+];
+
+// Queue events for everything that changed between two frames.
+fn diff(
+    id: u8,
+    old: &DeviceState,
+    new: &DeviceState,
+    events: &mut VecDeque<(u8, Event)>,
+) {
+    fn axis(v: f32) -> i8 {
+        (v * 127.0) as i8
+    }
+
+    for (btn, event) in BTN_EVENTS.iter() {
+        let is = new.btn(*btn);
+        if old.btn(*btn) != is {
+            events.push_back((id, event(is == Some(true))));
+        }
+    }
+
+    let axes: [(f32, f32, AxisEvent); 6] = [
+        (old.joy.0, new.joy.0, Event::MotionH),
+        (old.joy.1, new.joy.1, Event::MotionV),
+        (old.cam.0, new.cam.0, Event::CameraH),
+        (old.cam.1, new.cam.1, Event::CameraV),
+        (old.lrt.0, new.lrt.0, Event::Lz),
+        (old.lrt.1, new.lrt.1, Event::Rz),
+    ];
+    for (old, new, event) in axes.iter() {
+        if axis(*old) != axis(*new) {
+            events.push_back((id, event(axis(*new))));
+        }
+    }
+}
+
+// Queue events for buttons that don't have a `Btn`: Home, and the Xbox Share
+// button and paddles.
+fn diff_extra(
+    id: u8,
+    device: &Device,
+    old: &RawState,
+    new: &RawState,
+    events: &mut VecDeque<(u8, Event)>,
+) {
+    if new.key(BTN_MODE) && !old.key(BTN_MODE) {
+        events.push_back((id, Event::Exit));
+    }
+    if xbox::xbox(device.hardware_id) {
+        xbox::events(old, new, |event| events.push_back((id, event)));
+    }
+}
+
+// Get the `Btn` of a numbered joystick button (`BTN_TRIGGER` is 0), with
+// the north, east, south and west face buttons.
+fn joystick_btn(number: u16, [x, a, b, y]: [Btn; 4]) -> Option<Btn> {
+    Some(match number {
+        0 => x,
+        1 => a,
+        2 => b,
+        3 => y,
+        4 => Btn::L,
+        5 => Btn::R,
+        6 => Btn::W,
+        7 => Btn::Z,
+        8 => Btn::F,
+        9 => Btn::E,
+        12 => Btn::Up,
+        13 => Btn::Right,
+        14 => Btn::Down,
+        15 => Btn::Left,
+        _ => return None,
+    })
+}
+
+// Apply one raw event to the pending frame.  Returns `true` when the frame is
+// complete.
+fn joystick_map_event(
+    js: &EvdevEvent,
+    device: &Device,
+    state: &mut DeviceState,
+) -> bool {
+    fn edit<B: Into<u8>>(is: bool, state: &mut DeviceState, b: B) {
+        if is {
+            state.btns |= 1 << b.into();
+        } else {
+            state.btns &= !(1 << b.into());
+        }
+    }
+
+    // A mapping from the `MappingWizard` replaces the built-in one.
+    if js.ev_type != 0x00 {
+        if let Some(mapping) = device.mapping.read().unwrap().as_ref() {
+            mapping_event(mapping, js, device, state);
+            return false;
+        }
+    }
+
+    // Apply Mods
+    let a = if xbox::xbox(device.hardware_id) {
+        Btn::B
+    } else {
+        Btn::A
+    };
+
+    let b = if xbox::xbox(device.hardware_id) {
+        Btn::A
+    } else {
+        Btn::B
+    };
 
     let x = if device.hardware_id == 0x_054C_0268
     /* PS3 */
@@ -398,69 +1814,77 @@ fn joystick_poll_event(fd: i32, device: &mut Device) -> bool {
 
     // Get Events
     match js.ev_type {
+        // synchronization: SYN_REPORT completes the frame.  SYN_DROPPED is
+        // handled by `Controllers::event()`.
+        0x00 => return js.ev_code == 0,
         // button press / release (key)
         0x01 => {
-            //            println!("EV CODE {}", js.ev_code - 0x120);
-
             let is = js.ev_value == 1;
 
-            match js.ev_code - 0x120 {
-                // ABXY
-                0 | 19 => edit(is, device, x),
-                1 | 17 => edit(is, device, a),
-                2 | 16 => edit(is, device, b),
-                3 | 20 => edit(is, device, y),
-                // LT/RT
-                4 | 24 => edit(is, device, Btn::L),
-                5 | 25 => edit(is, device, Btn::R),
-                // LB/RB
-                6 | 22 => edit(is, device, Btn::W), // 6 is a guess.
-                7 | 23 => edit(is, device, Btn::Z),
-                // Select/Start
-                8 | 26 => edit(is, device, Btn::F), // 8 is a guess.
-                9 | 27 => edit(is, device, Btn::E),
-                // ?
-                10 => println!("Button 10 is Unknown"),
-                // D-PAD
-                12 | 256 => edit(is, device, Btn::Up),
-                13 | 259 => edit(is, device, Btn::Right),
-                14 | 257 => edit(is, device, Btn::Down),
-                15 | 258 => edit(is, device, Btn::Left),
-                // 16-17 already matched
-                18 => println!("Button 18 is Unknown"),
-                // 19-20 already matched
-                21 => println!("Button 21 is Unknown"),
-                // 22-27 already matched
-                28 => println!("Button 28 is Unknown"),
-                29 => edit(is, device, Btn::D),
-                30 => edit(is, device, Btn::C),
-                a => println!("Button {} is Unknown", a),
+            let btn = match js.ev_code as u16 {
+                // The kernel's gamepad layout (`BTN_GAMEPAD`), which
+                // drivers that follow its gamepad spec use.
+                0x130 => Some(b),      // BTN_SOUTH
+                0x131 => Some(a),      // BTN_EAST
+                0x133 => Some(x),      // BTN_NORTH
+                0x134 => Some(y),      // BTN_WEST
+                0x136 => Some(Btn::W), // BTN_TL
+                0x137 => Some(Btn::Z), // BTN_TR
+                0x138 => Some(Btn::L), // BTN_TL2
+                0x139 => Some(Btn::R), // BTN_TR2
+                0x13A => Some(Btn::F), // BTN_SELECT
+                0x13B => Some(Btn::E), // BTN_START
+                0x13D => Some(Btn::D), // BTN_THUMBL
+                0x13E => Some(Btn::C), // BTN_THUMBR
+                0x220 => Some(Btn::Up),
+                0x221 => Some(Btn::Down),
+                0x222 => Some(Btn::Left),
+                0x223 => Some(Btn::Right),
+                // Numbered joystick buttons (`BTN_JOYSTICK`), only on
+                // devices without the gamepad layout, in the GameCube
+                // adapter's order.
+                code @ 0x120..=0x12F if !device.gamepad => {
+                    joystick_btn(code - 0x120, [x, a, b, y])
+                }
+                // Unknown buttons (and `BTN_MODE`, which sends `Event::Exit`)
+                // are only in the `RawState`.
+                _ => None,
+            };
+            if let Some(btn) = btn {
+                edit(is, state, btn);
+            }
+
+            // Some Xbox D-pads are buttons instead of a hat.
+            if xbox::xbox(device.hardware_id) {
+                if let Some(btn) = xbox::dpad(js.ev_code as u16) {
+                    edit(is, state, btn);
+                }
+            }
+
+            // Nintendo's ZL and ZR are digital.
+            if joycon::nintendo(device.hardware_id) {
+                let value = if is { 1.0 } else { 0.0 };
+                match js.ev_code - 0x120 {
+                    24 => state.lrt.0 = value,
+                    25 => state.lrt.1 = value,
+                    _ => {}
+                }
             }
         }
         // axis move (abs)
         0x03 => {
-            let value = if device.hardware_id == 0x_0079_1844 {
-                // GameCube
-                let pad = (device.abs_max - device.abs_min) / 4;
-                transform(
-                    device.abs_min + pad,
-                    device.abs_max - pad,
-                    js.ev_value,
-                )
-            } else {
-                transform(device.abs_min, device.abs_max, js.ev_value)
-            };
-
-            let value2 = if device.hardware_id == 0x_0079_1844 {
-                // GameCube
-                transform2(32, 95, js.ev_value)
+            let kind = device.axis_kind(js.ev_code as u8);
+            // Triggers can't be centered, so a bipolar one is scaled like a
+            // unipolar one.
+            let trigger_kind = if kind == AxisKind::Bipolar {
+                AxisKind::Unipolar
             } else {
-                transform2(0, 127, js.ev_value)
+                kind
             };
-
-            // if value != 0 {
-            //     println!("{} {}", js.ev_code, value);
-            // }
+            let range = axis_range(device, js.ev_code as u8, kind);
+            let value = normalize(kind, range, js.ev_value);
+            let range = axis_range(device, js.ev_code as u8, trigger_kind);
+            let trigger = normalize(trigger_kind, range, js.ev_value);
 
             // For some reason this is different on the GameCube controller, so fix it.
             let (cam_x, cam_y, lrt_l, lrt_r) = match device.hardware_id {
@@ -469,69 +1893,197 @@ fn joystick_poll_event(fd: i32, device: &mut Device) -> bool {
             };
 
             match js.ev_code {
-                0 => afloat(&device.joyx, &|_| value),
-                1 => afloat(&device.joyy, &|_| value),
+                0 => state.joy.0 = value,
+                1 => state.joy.1 = value,
                 16 => {
                     if js.ev_value < 0 {
-                        edit(true, device, Btn::Left);
-                        edit(false, device, Btn::Right);
+                        edit(true, state, Btn::Left);
+                        edit(false, state, Btn::Right);
                     } else if js.ev_value > 0 {
-                        edit(false, device, Btn::Left);
-                        edit(true, device, Btn::Right);
+                        edit(false, state, Btn::Left);
+                        edit(true, state, Btn::Right);
                     } else {
-                        edit(false, device, Btn::Left);
-                        edit(false, device, Btn::Right);
+                        edit(false, state, Btn::Left);
+                        edit(false, state, Btn::Right);
                     }
                 }
                 17 => {
                     if js.ev_value < 0 {
-                        edit(true, device, Btn::Up);
-                        edit(false, device, Btn::Down);
+                        edit(true, state, Btn::Up);
+                        edit(false, state, Btn::Down);
                     } else if js.ev_value > 0 {
-                        edit(false, device, Btn::Up);
-                        edit(true, device, Btn::Down);
+                        edit(false, state, Btn::Up);
+                        edit(true, state, Btn::Down);
                     } else {
-                        edit(false, device, Btn::Up);
-                        edit(false, device, Btn::Down);
+                        edit(false, state, Btn::Up);
+                        edit(false, state, Btn::Down);
                     }
                 }
                 40 => {} // IGNORE: Duplicate axis.
                 a => {
                     if a == cam_x {
-                        afloat(&device.camx, &|_| {
-                            value
-                        });
+                        state.cam.0 = value;
                     } else if a == cam_y {
-                        afloat(&device.camy, &|_| {
-                            value
-                        });
+                        state.cam.1 = value;
                     } else if a == lrt_l {
-                        if value2 > 0.99 {
-                            edit(true, device, Btn::L)
-                        } else {
-                            edit(false, device, Btn::L)
-                        }
-                        afloat(&device.trgl, &|_| {
-                            value2
-                        });
+                        let held = trigger_held(device, state, Btn::L, trigger);
+                        edit(held, state, Btn::L);
+                        state.lrt.0 = trigger;
                     } else if a == lrt_r {
-                        if value2 > 0.99 {
-                            edit(true, device, Btn::R)
-                        } else {
-                            edit(false, device, Btn::R)
-                        }
-                        afloat(&device.trgr, &|_| {
-                            value2
-                        });
+                        let held = trigger_held(device, state, Btn::R, trigger);
+                        edit(held, state, Btn::R);
+                        state.lrt.1 = trigger;
                     }
-                } // println!("Unknown Axis: {}", a),
+                }
             }
         }
         // ignore
         _ => {}
     }
 
-    true
+    false
+}
+
+// Apply a button or axis event through a mapping.
+fn mapping_event(
+    mapping: &Mapping,
+    js: &EvdevEvent,
+    device: &Device,
+    state: &mut DeviceState,
+) {
+    fn edit(is: bool, state: &mut DeviceState, btn: Btn) {
+        if is {
+            state.btns |= 1 << btn as u8;
+        } else {
+            state.btns &= !(1 << btn as u8);
+        }
+    }
+
+    let code = js.ev_code as u16;
+    for (target, control) in mapping.controls() {
+        let trigger = matches!(target, Target::Axis(Axis::TrgL | Axis::TrgR));
+        let value = match (js.ev_type, *control) {
+            (0x01, Control::Button(c)) if c == code => {
+                (js.ev_value != 0) as u8 as f32
+            }
+            (0x03, Control::Axis(c, inverted)) if u16::from(c) == code => {
+                // Triggers can't be centered, like in the built-in mapping.
+                let kind = match device.axis_kind(c) {
+                    _ if trigger && inverted => AxisKind::Inverted,
+                    AxisKind::Bipolar if trigger => AxisKind::Unipolar,
+                    kind => kind,
+                };
+                let value =
+                    normalize(kind, axis_range(device, c, kind), js.ev_value);
+                if inverted && !trigger {
+                    -value
+                } else {
+                    value
+                }
+            }
+            (0x03, Control::HalfAxis(c, positive)) if u16::from(c) == code => {
+                // Hats are -1, 0 or 1, other axes count past halfway.
+                let value = if (0x10..=0x17).contains(&c) {
+                    js.ev_value.signum() as f32
+                } else {
+                    let range = axis_range(device, c, AxisKind::Bipolar);
+                    let value =
+                        normalize(AxisKind::Bipolar, range, js.ev_value);
+                    (value * 2.0).trunc().clamp(-1.0, 1.0)
+                };
+                if positive {
+                    value.max(0.0)
+                } else {
+                    (-value).max(0.0)
+                }
+            }
+            _ => continue,
+        };
+
+        let axis = match *target {
+            Target::Btn(btn) => {
+                edit(value > 0.5, state, btn);
+                continue;
+            }
+            Target::Axis(Axis::JoyX) => &mut state.joy.0,
+            Target::Axis(Axis::JoyY) => &mut state.joy.1,
+            Target::Axis(Axis::CamX) => &mut state.cam.0,
+            Target::Axis(Axis::CamY) => &mut state.cam.1,
+            Target::Axis(Axis::TrgL) => &mut state.lrt.0,
+            Target::Axis(Axis::TrgR) => &mut state.lrt.1,
+        };
+        *axis = value;
+        // Analog triggers are buttons too, unless they have their own.
+        let btn = match *target {
+            Target::Axis(Axis::TrgL) => Btn::L,
+            Target::Axis(Axis::TrgR) => Btn::R,
+            _ => continue,
+        };
+        if mapping.get(Target::Btn(btn)).is_none() {
+            let held = trigger_held(device, state, btn, value);
+            edit(held, state, btn);
+        }
+    }
+}
+
+// Get the range to scale an axis over: minimum, center (if it's been
+// measured) and maximum.
+fn axis_range(
+    device: &Device,
+    code: u8,
+    kind: AxisKind,
+) -> (i32, Option<i32>, i32) {
+    if let Some(range) = device.calibrated(code) {
+        return (range.min, Some(range.center), range.max);
+    }
+    if device.hardware_id == 0x_0079_1844 {
+        // GameCube
+        return if kind == AxisKind::Bipolar {
+            let pad = (device.abs_max - device.abs_min) / 4;
+            (device.abs_min + pad, None, device.abs_max - pad)
+        } else {
+            (32, None, 95)
+        };
+    }
+    match device.info.abs(code).filter(|abs| abs.max > abs.min) {
+        // Use the range the axis reports, if there is one.
+        Some(abs) => (abs.min, None, abs.max),
+        None if kind == AxisKind::Bipolar => {
+            (device.abs_min, None, device.abs_max)
+        }
+        None => (0, None, 127),
+    }
+}
+
+// Scale a raw axis value for the mapped state, by its kind.
+fn normalize(
+    kind: AxisKind,
+    (min, center, max): (i32, Option<i32>, i32),
+    val: i32,
+) -> f32 {
+    match kind {
+        AxisKind::Bipolar => match center {
+            Some(center) => transform_centered(min, center, max, val),
+            None => transform(min, max, val),
+        },
+        AxisKind::Unipolar => unipolar(min, max, val),
+        AxisKind::Inverted => 1.0 - unipolar(min, max, val),
+    }
+}
+
+// Check if a trigger pulled to `value` holds its button, with hysteresis.
+fn trigger_held(
+    device: &Device,
+    state: &DeviceState,
+    btn: Btn,
+    value: f32,
+) -> bool {
+    let threshold = if state.btn(btn) == Some(true) {
+        &device.release
+    } else {
+        &device.press
+    };
+    value > gfloat(threshold)
 }
 
 fn deadzone(min: i32, max: i32, val: i32) -> (i32, i32) {
@@ -556,21 +2108,88 @@ fn deadzone(min: i32, max: i32, val: i32) -> (i32, i32) {
 
 fn transform(min: i32, max: i32, val: i32) -> f32 {
     let (value, full) = deadzone(min, max, val);
+    // No range to move in (the device doesn't have this axis).
+    if full <= 0 {
+        return 0.0;
+    }
     // Modify integer range from (-(full) thru (full)) to -127 to 127
-    ((value * 127) / full).max(-127).min(127) as f32 / 127.0
+    ((value * 127) / full).clamp(-127, 127) as f32 / 127.0
+}
+
+// Like `transform()`, but scaling each side of a measured center on its own.
+fn transform_centered(min: i32, center: i32, max: i32, val: i32) -> f32 {
+    if val < center {
+        transform(min, center * 2 - min, val)
+    } else {
+        transform(center * 2 - max, max, val)
+    }
 }
 
-fn transform2(min: i32, max: i32, val: i32) -> f32 {
-    // Modify integer range from (-(full) thru (full)) to 0 to 255
-    ((val * 255) / (max - min)).max(0).min(255) as f32 / 255.0
+// Scale from (min thru max) to 0.0 thru 1.0.
+fn unipolar(min: i32, max: i32, val: i32) -> f32 {
+    if max <= min {
+        return 0.0;
+    }
+    let range = (max as f32) - (min as f32);
+    (((val as f32) - (min as f32)) / range).clamp(0.0, 1.0)
 }
 
 #[cfg(test)]
 mod tests {
-    /*    use super::*;
+    use super::*;
+    use crate::backend::{drain, mock_port};
+
+    // Axis ranges reported by real controllers.
+    const RANGES: [(i32, i32); 6] = [
+        (-100, 100),
+        (-128, 127),
+        (0, 255),
+        (0, 1023),
+        (32, 95),
+        (-32768, 32767),
+    ];
+
+    fn device(hardware_id: u32, abs: Vec<(u8, AbsInfo)>) -> Device {
+        Device::new(
+            0,
+            DeviceInfo {
+                hardware_id,
+                name: String::new(),
+                uniq: String::new(),
+                abs,
+                keys: Vec::new(),
+                path: String::new(),
+            },
+        )
+    }
+
+    fn range(min: i32, max: i32) -> AbsInfo {
+        AbsInfo {
+            min,
+            max,
+            ..Default::default()
+        }
+    }
+
+    // Apply one evdev event, returning the new state.
+    fn map(
+        device: &Device,
+        state: &mut DeviceState,
+        ev_type: i16,
+        ev_code: i16,
+        ev_value: i32,
+    ) -> DeviceState {
+        let js = EvdevEvent {
+            ev_type,
+            ev_code,
+            ev_value,
+        };
+        joystick_map_event(&js, device, state);
+        *state
+    }
 
     #[test]
-    fn transform_test() {
+    fn deadzone_test() {
         let a = deadzone(-100, 100, 100);
         assert_eq!(a.0, a.1);
         assert_eq!(75, a.1);
@@ -579,14 +2198,499 @@ mod tests {
         assert_eq!(75, b.1);
         let c = deadzone(-100, 100, 0);
         assert_eq!(c.0, 0);
-        assert_eq!(75, b.1);
+        assert_eq!(75, c.1);
 
-        assert_eq!(transform(-100, 100, 100), 127);
-        assert_eq!(transform(-100, 100, -100), -127);
-        assert_eq!(transform(-100, 100, 0), 0);
+        // Everything within 1/8th of the range from the center is zero.
+        for val in -24..=24 {
+            assert_eq!(deadzone(-100, 100, val).0, 0);
+        }
+        assert_eq!(deadzone(-100, 100, 26).0, 1);
+        assert_eq!(deadzone(-100, 100, -26).0, -1);
+    }
+
+    #[test]
+    fn transform_test() {
+        assert_eq!(transform(-100, 100, 100), 1.0);
+        assert_eq!(transform(-100, 100, -100), -1.0);
+        assert_eq!(transform(-100, 100, 0), 0.0);
+
+        assert_eq!(transform(-128, 127, 127), 1.0);
+        assert_eq!(transform(-128, 127, 0), 0.0);
+        assert_eq!(transform(-128, 127, -128), -1.0);
+
+        // Out of range values are clamped.
+        assert_eq!(transform(-100, 100, 1000), 1.0);
+        assert_eq!(transform(-100, 100, -1000), -1.0);
+    }
 
-        assert_eq!(transform(-128, 127, 127), 127);
-        assert_eq!(transform(-128, 127, 0), 0);
-        assert_eq!(transform(-128, 127, -128), -127);
-    }*/
+    #[test]
+    fn transform_properties() {
+        for &(min, max) in RANGES.iter() {
+            let midpt = min + ((max - min) >> 1);
+
+            // Range bounds: the ends of the range are full tilt.
+            assert_eq!(transform(min, max, min), -1.0, "{}..{}", min, max);
+            assert_eq!(transform(min, max, max), 1.0, "{}..{}", min, max);
+            assert_eq!(transform(min, max, midpt), 0.0, "{}..{}", min, max);
+
+            let mut last = -1.0;
+            for val in min..=max {
+                let value = transform(min, max, val);
+                assert!((-1.0..=1.0).contains(&value), "{}", val);
+                // Monotonicity.
+                assert!(value >= last, "{}..{} at {}", min, max, val);
+                last = value;
+                // Symmetry around the center.
+                let mirror = 2 * midpt - val;
+                if mirror >= min && mirror <= max {
+                    assert_eq!(value, -transform(min, max, mirror));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unipolar_properties() {
+        for &(min, max) in RANGES.iter() {
+            assert_eq!(unipolar(min, max, min), 0.0, "{}..{}", min, max);
+            assert_eq!(unipolar(min, max, max), 1.0, "{}..{}", min, max);
+
+            let mut last = 0.0;
+            for val in min..=max {
+                let value = unipolar(min, max, val);
+                assert!((0.0..=1.0).contains(&value), "{}", val);
+                assert!(value >= last, "{}..{} at {}", min, max, val);
+                last = value;
+            }
+        }
+    }
+
+    // A xorshift generator, to check properties on many made up ranges and
+    // values (the same ones each run).
+    struct Rng(u64);
+
+    impl Rng {
+        // A number from `lo` thru `hi`.
+        fn range(&mut self, lo: i32, hi: i32) -> i32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            let span = i64::from(hi) - i64::from(lo) + 1;
+            (i64::from(lo) + (self.0 % span as u64) as i64) as i32
+        }
+    }
+
+    #[test]
+    fn generated_properties() {
+        let mut rng = Rng(0x_5717_C4E5);
+        for _ in 0..10_000 {
+            // An axis range, and values in it or past either end.
+            let min = rng.range(-70_000, 70_000);
+            let max = min + rng.range(2, 140_000);
+            let width = max - min;
+            let a = rng.range(min - width, max + width);
+            let b = rng.range(min - width, max + width);
+            let (lo, hi) = (a.min(b), a.max(b));
+            let case = (min, max, lo, hi);
+
+            // Range bounds.
+            let (low, high) =
+                (transform(min, max, lo), transform(min, max, hi));
+            assert!((-1.0..=1.0).contains(&low), "{:?}", case);
+            assert!((-1.0..=1.0).contains(&high), "{:?}", case);
+            assert_eq!(transform(min, max, min), -1.0, "{:?}", case);
+            assert_eq!(transform(min, max, max), 1.0, "{:?}", case);
+            // Monotonicity.
+            assert!(low <= high, "{:?}", case);
+            // Symmetry around the center.
+            let mirror = 2 * (min + (width >> 1)) - lo;
+            assert_eq!(low, -transform(min, max, mirror), "{:?}", case);
+
+            let (low, high) = (unipolar(min, max, lo), unipolar(min, max, hi));
+            assert!((0.0..=1.0).contains(&low), "{:?}", case);
+            assert!((0.0..=1.0).contains(&high), "{:?}", case);
+            assert_eq!(unipolar(min, max, min), 0.0, "{:?}", case);
+            assert_eq!(unipolar(min, max, max), 1.0, "{:?}", case);
+            assert!(low <= high, "{:?}", case);
+
+            // A measured center anywhere inside the range.
+            let center = rng.range(min + 1, max - 1);
+            let centered = |val| transform_centered(min, center, max, val);
+            let (low, high) = (centered(lo), centered(hi));
+            assert!((-1.0..=1.0).contains(&low), "{:?} {}", case, center);
+            assert!((-1.0..=1.0).contains(&high), "{:?} {}", case, center);
+            assert_eq!(centered(min), -1.0, "{:?} {}", case, center);
+            assert_eq!(centered(center), 0.0, "{:?} {}", case, center);
+            assert_eq!(centered(max), 1.0, "{:?} {}", case, center);
+            assert!(low <= high, "{:?} {}", case, center);
+        }
+    }
+
+    #[test]
+    fn triggers() {
+        // Triggers use their own range, not the stick's.
+        let pad = device(
+            0x_054C_0268,
+            vec![(0, range(0, 255)), (2, range(0, 255)), (5, range(0, 255))],
+        );
+        let mut state = DeviceState::default();
+        assert_eq!(map(&pad, &mut state, 3, 2, 0).lrt.0, 0.0);
+        assert!(!state.btn(Btn::L).unwrap());
+        let half = map(&pad, &mut state, 3, 2, 128).lrt.0;
+        assert!(half > 0.45 && half < 0.55, "{}", half);
+        assert!(!state.btn(Btn::L).unwrap());
+        assert_eq!(map(&pad, &mut state, 3, 2, 255).lrt.0, 1.0);
+        assert!(state.btn(Btn::L).unwrap());
+        assert_eq!(map(&pad, &mut state, 3, 5, 255).lrt.1, 1.0);
+        assert!(state.btn(Btn::R).unwrap());
+
+        // The GameCube's triggers rest at 32, not 0.
+        let gamecube = device(0x_0079_1844, vec![(0, range(0, 255))]);
+        let mut state = DeviceState::default();
+        assert_eq!(map(&gamecube, &mut state, 3, 3, 32).lrt.0, 0.0);
+        assert_eq!(map(&gamecube, &mut state, 3, 3, 95).lrt.0, 1.0);
+        assert_eq!(map(&gamecube, &mut state, 3, 4, 32).lrt.1, 0.0);
+    }
+
+    #[test]
+    fn axis_kinds() {
+        let rest = |min, max, value| AbsInfo {
+            value,
+            ..range(min, max)
+        };
+        let info = DeviceInfo {
+            abs: vec![
+                (0x00, rest(0, 255, 0)),     // ABS_X
+                (0x02, rest(0, 255, 0)),     // ABS_Z
+                (0x05, rest(-128, 127, 0)),  // ABS_RZ
+                (0x09, rest(0, 1023, 1023)), // ABS_GAS
+            ],
+            ..Default::default()
+        };
+        assert_eq!(info.axis_kind(0x00), AxisKind::Bipolar);
+        assert_eq!(info.axis_kind(0x02), AxisKind::Unipolar);
+        assert_eq!(info.axis_kind(0x05), AxisKind::Bipolar);
+        assert_eq!(info.axis_kind(0x09), AxisKind::Inverted);
+        assert_eq!(info.axis_kind(0x0A), AxisKind::Unipolar);
+        let gamecube = DeviceInfo {
+            hardware_id: 0x_0079_1844,
+            ..Default::default()
+        };
+        assert_eq!(gamecube.axis_kind(0x02), AxisKind::Bipolar);
+        assert_eq!(gamecube.axis_kind(0x03), AxisKind::Unipolar);
+
+        let (mock, mut port) = mock_port();
+        let id = mock.connect(info);
+        // Centered trigger, and pedal pressed halfway.
+        mock.event(id, 0x03, 0x05, 0);
+        mock.event(id, 0x03, 0x09, 512);
+        mock.sync(id);
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        let (_, r) = device.lrt().unwrap();
+        assert!((r - 0.5).abs() < 0.01, "{}", r);
+        assert!((device.axis(0x09).unwrap() - 0.5).abs() < 0.01);
+        assert!(device.axis(0x05).unwrap().abs() < 0.01);
+
+        port.set_axis_kind(0, 0x09, AxisKind::Unipolar);
+        port.set_axis_kind(0, 0x02, AxisKind::Inverted);
+        let device = port.get(0).unwrap();
+        assert_eq!(device.axis_kind(0x02), AxisKind::Inverted);
+        mock.event(id, 0x03, 0x09, 1023);
+        mock.event(id, 0x03, 0x02, 255);
+        mock.sync(id);
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        assert_eq!(device.axis(0x09), Some(1.0));
+        assert_eq!(device.lrt(), Some((0.0, r)));
+        assert_eq!(device.btn(Btn::L), Some(false));
+
+        // Trigger buttons, with hysteresis.
+        port.set_trigger_thresholds(0, 0.6, 0.3);
+        let mut pull = |value, held| {
+            mock.event(id, 0x03, 0x02, 255 - value);
+            mock.sync(id);
+            drain(&mut port);
+            let device = port.get(0).unwrap();
+            assert_eq!(device.btn(Btn::L), Some(held), "{}", value);
+        };
+        pull(128, false);
+        pull(160, true);
+        pull(100, true);
+        pull(70, false);
+        pull(128, false);
+    }
+
+    #[test]
+    fn stick_shapes() {
+        let (mock, mut port) = mock_port();
+        let gamecube = DeviceInfo {
+            hardware_id: 0x_0079_1844,
+            ..Default::default()
+        };
+        assert_eq!(gamecube.stick_shape(), StickShape::Octagon);
+        let id = mock.connect(DeviceInfo {
+            abs: vec![(0, range(-128, 127)), (1, range(-128, 127))],
+            ..Default::default()
+        });
+        // A square gate's corner.
+        mock.event(id, 0x03, 0, 127);
+        mock.event(id, 0x03, 1, 127);
+        mock.sync(id);
+        drain(&mut port);
+        let (x, y) = port.get(0).unwrap().joy().unwrap();
+        assert!((x.hypot(y) - 1.0).abs() < 1e-3, "{} {}", x, y);
+
+        // Only the published state is shaped, so moving one axis doesn't
+        // shape the other twice.
+        port.set_stick_shape(0, StickShape::SquareToDisc);
+        mock.abs(id, 0, 0);
+        mock.abs(id, 0, 127);
+        drain(&mut port);
+        let (x, y) = port.get(0).unwrap().joy().unwrap();
+        assert!((x.hypot(y) - 1.0).abs() < 1e-3, "{} {}", x, y);
+
+        port.set_stick_shape(0, StickShape::Raw);
+        mock.abs(id, 1, 127);
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().joy(), Some((1.0, 1.0)));
+    }
+
+    #[test]
+    #[cfg(all(not(target_os = "macos"), unix))]
+    fn with_paths() {
+        let missing = Port::with_paths(&["/dev/input/missing"]);
+        assert_eq!(missing.err().unwrap().kind(), io::ErrorKind::NotFound);
+        let not_evdev = Port::with_paths(&["/dev/null"]);
+        let error = not_evdev.err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let mut none = Port::with_paths::<&str>(&[]).unwrap();
+        assert_eq!(none.try_poll(), None);
+    }
+
+    #[test]
+    fn dpad_hat() {
+        let pad = device(0, vec![(0, range(-128, 127))]);
+        let mut state = DeviceState::default();
+        let dpad = |state: DeviceState| {
+            [Btn::Up, Btn::Down, Btn::Left, Btn::Right]
+                .iter()
+                .map(|btn| state.btn(*btn).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // ABS_HAT0X
+        let left = map(&pad, &mut state, 3, 16, -1);
+        assert_eq!(dpad(left), [false, false, true, false]);
+        let right = map(&pad, &mut state, 3, 16, 1);
+        assert_eq!(dpad(right), [false, false, false, true]);
+        // ABS_HAT0Y, while still holding right.
+        let up = map(&pad, &mut state, 3, 17, -1);
+        assert_eq!(dpad(up), [true, false, false, true]);
+        let down = map(&pad, &mut state, 3, 17, 1);
+        assert_eq!(dpad(down), [false, true, false, true]);
+        map(&pad, &mut state, 3, 16, 0);
+        let center = map(&pad, &mut state, 3, 17, 0);
+        assert_eq!(dpad(center), [false, false, false, false]);
+        assert_eq!(center.btns, 0);
+    }
+
+    #[test]
+    fn face_button_swaps() {
+        // (hardware ID, button for BTN_SOUTH, BTN_EAST, BTN_NORTH, BTN_WEST)
+        let cases = [
+            (0x_0000_0000, Btn::B, Btn::A, Btn::X, Btn::Y),
+            (0x_0E6F_0501, Btn::A, Btn::B, Btn::X, Btn::Y), // Xbox
+            (0x_045E_0B12, Btn::A, Btn::B, Btn::X, Btn::Y), // Series
+            (0x_054C_0268, Btn::B, Btn::A, Btn::Y, Btn::X), // PS3
+            (0x_054C_05C4, Btn::B, Btn::A, Btn::X, Btn::Y), // PS4
+            (0x_054C_0CE6, Btn::B, Btn::A, Btn::X, Btn::Y), // PS5
+        ];
+
+        for &(hardware_id, south, east, north, west) in cases.iter() {
+            let pad = device(hardware_id, vec![(0, range(-128, 127))]);
+            for &(code, btn) in
+                [(0x130, south), (0x131, east), (0x133, north), (0x134, west)]
+                    .iter()
+            {
+                let mut state = DeviceState::default();
+                let pressed = map(&pad, &mut state, 1, code, 1);
+                assert_eq!(pressed.btns, 1 << btn as u8, "{:X}", code);
+                let released = map(&pad, &mut state, 1, code, 0);
+                assert_eq!(released.btns, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn generic_gamepad() {
+        let pad = Device::new(
+            0,
+            DeviceInfo {
+                hardware_id: 0x_1234_5678,
+                keys: (0x130..=0x13E).chain(0x220..=0x223).collect(),
+                ..Default::default()
+            },
+        );
+        let cases = [
+            (0x136, Btn::W),
+            (0x137, Btn::Z),
+            (0x138, Btn::L),
+            (0x139, Btn::R),
+            (0x13A, Btn::F),
+            (0x13B, Btn::E),
+            (0x13D, Btn::D),
+            (0x13E, Btn::C),
+            (0x220, Btn::Up),
+            (0x221, Btn::Down),
+            (0x222, Btn::Left),
+            (0x223, Btn::Right),
+        ];
+        for &(code, btn) in cases.iter() {
+            let mut state = DeviceState::default();
+            let pressed = map(&pad, &mut state, 1, code, 1);
+            assert_eq!(pressed.btns, 1 << btn as u8, "{:X}", code);
+        }
+        // BTN_MODE and numbered joystick buttons have no `Btn` on a gamepad.
+        let mut state = DeviceState::default();
+        assert_eq!(map(&pad, &mut state, 1, 0x13C, 1).btns, 0);
+        assert_eq!(map(&pad, &mut state, 1, 0x120, 1).btns, 0);
+
+        // But they do on a joystick.
+        let stick = device(0x_1234_5678, vec![]);
+        let mut state = DeviceState::default();
+        let trigger = map(&stick, &mut state, 1, 0x120, 1);
+        assert_eq!(trigger.btns, 1 << Btn::X as u8);
+    }
+
+    #[test]
+    fn frames() {
+        let pad = device(0, vec![(0, range(-128, 127))]);
+        let mut state = DeviceState::default();
+        let js = EvdevEvent {
+            ev_type: 0x01,
+            ev_code: 0x130,
+            ev_value: 1,
+        };
+        assert!(!joystick_map_event(&js, &pad, &mut state));
+        let syn = EvdevEvent::default();
+        assert!(joystick_map_event(&syn, &pad, &mut state));
+    }
+
+    #[test]
+    fn dropped() {
+        let (mock, mut port) = mock_port();
+        // The GameCube's octagon gate shapes its sticks.
+        let id = mock.connect(DeviceInfo {
+            hardware_id: 0x_0079_1844,
+            abs: vec![(0, range(0, 255)), (1, range(0, 255))],
+            ..Default::default()
+        });
+        mock.event(id, 0x03, 0, 160);
+        mock.event(id, 0x03, 1, 110);
+        mock.sync(id);
+        drain(&mut port);
+        let pushed = port.get(0).unwrap().snapshot();
+
+        // SYN_DROPPED throws away the partial frame, and the rest of it up
+        // to the next SYN_REPORT.
+        mock.event(id, 0x03, 0, 128);
+        mock.event(id, 0x00, 3, 0);
+        mock.event(id, 0x03, 1, 128);
+        mock.sync(id);
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().snapshot(), pushed);
+
+        // Without shaping the sticks again.
+        mock.event(id, 0x01, 0x120, 1);
+        mock.sync(id);
+        drain(&mut port);
+        let state = port.get(0).unwrap().snapshot();
+        assert_ne!(state.btns, 0);
+        assert_eq!(state.joy, pushed.joy);
+    }
+
+    #[test]
+    fn raw_state() {
+        let (mock, mut port) = mock_port();
+        let pad = mock.connect(DeviceInfo {
+            abs: vec![(0x28, range(0, 255))],
+            ..Default::default()
+        });
+        // A paddle (BTN_TRIGGER_HAPPY1) and a button with no `Btn`.
+        mock.event(pad, 0x01, 0x2C0, 1);
+        mock.event(pad, 0x01, 0x13C, 1);
+        mock.event(pad, 0x03, 0x28, 200);
+        // Not published until the frame ends.
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().raw(), RawState::default());
+
+        mock.sync(pad);
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        let raw = device.raw();
+        assert!(raw.key(0x2C0));
+        assert!(!raw.key(0x2C1));
+        assert!(!raw.key(u16::MAX));
+        assert_eq!(raw.keys().collect::<Vec<_>>(), [0x13C, 0x2C0]);
+        assert_eq!(raw.abs(0x28), 200);
+        assert_eq!(
+            device.abs(0x28).map(|abs| (abs.value, abs.max)),
+            Some((200, 255))
+        );
+        assert_eq!(device.abs(0x29), None);
+        assert_eq!(device.snapshot().btns, 0);
+    }
+
+    #[test]
+    fn flight_stick() {
+        let (mock, mut port) = mock_port();
+        port.raw_events(true);
+        let throttle = mock.connect(DeviceInfo {
+            abs: vec![
+                (0x06, range(0, 255)),    // ABS_THROTTLE
+                (0x07, range(-512, 511)), // ABS_RUDDER
+                (0x12, range(-1, 1)),     // ABS_HAT1X
+                (0x13, range(-1, 1)),     // ABS_HAT1Y
+            ],
+            keys: vec![0x2C5],
+            ..Default::default()
+        });
+        assert_eq!(port.try_poll(), Some((0, Event::Connect)));
+        let info = port.get(0).unwrap().info();
+        assert_eq!(info.hats(), 1);
+
+        mock.event(throttle, 0x03, 0x06, 255);
+        mock.event(throttle, 0x03, 0x12, 1);
+        mock.event(throttle, 0x03, 0x13, -1);
+        mock.event(throttle, 0x01, 0x2C5, 1);
+        mock.sync(throttle);
+        let events = drain(&mut port);
+        assert_eq!(
+            events,
+            [
+                (0, Event::Button(0x2C5, true)),
+                (0, Event::Axis(0x06, 1.0)),
+                (0, Event::Hat(1, Some(Direction::UpRight))),
+            ]
+        );
+
+        let device = port.get(0).unwrap();
+        assert_eq!(device.axis(0x06), Some(1.0));
+        assert!(device.axis(0x07).unwrap().abs() < 0.01);
+        assert_eq!(device.axis(0x08), None);
+        assert_eq!(device.hat(1), Some(Direction::UpRight));
+        assert_eq!(device.hat(0), None);
+        assert!(device.button(0x2C5));
+
+        // Only directions that change are sent.
+        mock.event(throttle, 0x03, 0x13, 0);
+        mock.sync(throttle);
+        assert_eq!(
+            port.try_poll(),
+            Some((0, Event::Hat(1, Some(Direction::Right))))
+        );
+        assert_eq!(port.try_poll(), None);
+    }
 }
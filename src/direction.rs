@@ -0,0 +1,450 @@
+use crate::devices::BtnEvent;
+use crate::{Btn, Event};
+
+use std::f32::consts::PI;
+
+/// The position of a joystick, from `Device::joy()` or `Device::cam()`, for
+/// getting directions out of it:
+///
+/// ```norun
+/// if let Some(joy) = port.get(0).and_then(|device| device.joy()) {
+///     let slice = Stick::from(joy).sector(6, 0.5);
+/// }
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Stick {
+    /// Left (-1.0) to right (1.0).
+    pub x: f32,
+    /// Up (-1.0) to down (1.0).
+    pub y: f32,
+}
+
+impl From<(f32, f32)> for Stick {
+    fn from((x, y): (f32, f32)) -> Self {
+        Stick { x, y }
+    }
+}
+
+impl Stick {
+    /// How far the stick is pushed, from 0.0 (centered) to 1.0 (all the
+    /// way).
+    pub fn magnitude(self) -> f32 {
+        self.x.hypot(self.y).min(1.0)
+    }
+
+    /// Which way the stick is pushed, in radians clockwise from up (0.0 to
+    /// 2π).
+    pub fn angle(self) -> f32 {
+        let angle = self.x.atan2(-self.y);
+        if angle < 0.0 {
+            angle + 2.0 * PI
+        } else {
+            angle
+        }
+    }
+
+    /// Which of `slices` equal slices of a radial menu the stick points at,
+    /// or `None` if it's pushed less than `deadzone`.  Slice 0 is centered
+    /// on up, and they go clockwise.
+    pub fn sector(self, slices: u8, deadzone: f32) -> Option<u8> {
+        if slices == 0 || self.magnitude() < deadzone {
+            return None;
+        }
+        let width = 2.0 * PI / slices as f32;
+        let sector = ((self.angle() + width / 2.0) / width) as u8;
+        Some(sector % slices)
+    }
+
+    /// The 4-way direction, or `None` if it's pushed less than `deadzone`.
+    pub fn direction4(self, deadzone: f32) -> Option<Direction> {
+        Some(Direction::ALL[self.sector(4, deadzone)? as usize * 2])
+    }
+
+    /// The 8-way direction, or `None` if it's pushed less than `deadzone`.
+    pub fn direction8(self, deadzone: f32) -> Option<Direction> {
+        Some(Direction::ALL[self.sector(8, deadzone)? as usize])
+    }
+
+    /// Map the stick's position from the shape of its gate to the unit
+    /// circle.
+    pub fn shaped(self, shape: StickShape) -> Stick {
+        let Stick { x, y } = self;
+        match shape {
+            StickShape::Raw => self,
+            StickShape::Circle => self.clamped(1.0),
+            StickShape::SquareToDisc => {
+                let (x, y) = (x.clamp(-1.0, 1.0), y.clamp(-1.0, 1.0));
+                Stick {
+                    x: x * (1.0 - y * y / 2.0).sqrt(),
+                    y: y * (1.0 - x * x / 2.0).sqrt(),
+                }
+            }
+            StickShape::Octagon => {
+                // How far the nearest notch is, and how far the edge of the
+                // gate is at that angle.
+                let notch = PI / 4.0;
+                let off = self.angle() % notch;
+                let off = off.min(notch - off);
+                let edge = (PI / 8.0).cos() / (PI / 8.0 - off).cos();
+                Stick {
+                    x: x / edge,
+                    y: y / edge,
+                }
+                .clamped(1.0)
+            }
+        }
+    }
+
+    // Scale down to `max` if it's further from the center.
+    fn clamped(self, max: f32) -> Stick {
+        let magnitude = self.x.hypot(self.y);
+        if magnitude <= max {
+            return self;
+        }
+        Stick {
+            x: self.x * max / magnitude,
+            y: self.y * max / magnitude,
+        }
+    }
+}
+
+/// The shape of a stick's gate, and how it's mapped to a circle so that
+/// every direction reaches (and doesn't go past) a magnitude of 1.0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StickShape {
+    /// Leave the position as the device reports it.
+    Raw,
+    /// A round gate: only clamp to the unit circle.
+    Circle,
+    /// A square gate: map the square onto the unit circle, so the corners
+    /// are at the diagonals of the circle.
+    SquareToDisc,
+    /// An octagonal gate with notches in the 8 directions, like GameCube
+    /// sticks: push the edges between the notches out to the unit circle.
+    Octagon,
+}
+
+impl StickShape {
+    pub(crate) fn from_u8(shape: u8) -> Self {
+        match shape {
+            0 => StickShape::Raw,
+            2 => StickShape::SquareToDisc,
+            3 => StickShape::Octagon,
+            _ => StickShape::Circle,
+        }
+    }
+}
+
+/// A digital direction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// ↑
+    Up,
+    /// ↗
+    UpRight,
+    /// →
+    Right,
+    /// ↘
+    DownRight,
+    /// ↓
+    Down,
+    /// ↙
+    DownLeft,
+    /// ←
+    Left,
+    /// ↖
+    UpLeft,
+}
+
+impl Direction {
+    // Clockwise from up, so the index is the 8-way sector.
+    const ALL: [Direction; 8] = [
+        Direction::Up,
+        Direction::UpRight,
+        Direction::Right,
+        Direction::DownRight,
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+        Direction::UpLeft,
+    ];
+
+    /// Check if this direction includes a D-pad button, so `UpRight`
+    /// includes `Btn::Up` and `Btn::Right`.
+    pub fn has(self, btn: Btn) -> bool {
+        use Direction::*;
+
+        match btn {
+            Btn::Up => matches!(self, UpLeft | Up | UpRight),
+            Btn::Right => matches!(self, UpRight | Right | DownRight),
+            Btn::Down => matches!(self, DownRight | Down | DownLeft),
+            Btn::Left => matches!(self, DownLeft | Left | UpLeft),
+            _ => false,
+        }
+    }
+
+    fn sector(self) -> usize {
+        Direction::ALL.iter().position(|d| *d == self).unwrap()
+    }
+}
+
+/// A virtual D-pad driven by a stick, with hysteresis so that the direction
+/// doesn't flicker when the stick is near the edge of a direction:
+///
+/// ```norun
+/// let mut dpad = Dpad::eight();
+/// // Each frame:
+/// for event in dpad.update(device.joy().unwrap_or_default()) {
+///     // `Event::Up(true)`, `Event::Left(false)`, ...
+/// }
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct Dpad {
+    // 4 or 8 ways.
+    ways: u8,
+    // How far the stick has to be pushed to press a direction.
+    press: f32,
+    // How far back the stick has to come to release it.
+    release: f32,
+    // How far past the edge of a direction (as a fraction of its width) the
+    // stick has to go to change direction.
+    margin: f32,
+    current: Option<Direction>,
+}
+
+impl Dpad {
+    /// A D-pad with 4 directions (no diagonals).
+    pub fn four() -> Self {
+        Self::new(4)
+    }
+
+    /// A D-pad with 8 directions (including diagonals).
+    pub fn eight() -> Self {
+        Self::new(8)
+    }
+
+    fn new(ways: u8) -> Self {
+        Dpad {
+            ways,
+            press: 0.5,
+            release: 0.4,
+            margin: 0.125,
+            current: None,
+        }
+    }
+
+    /// Set how far the stick has to be pushed to press a direction (0.5 by
+    /// default), and how far back it has to come to release it (0.4 by
+    /// default).
+    pub fn thresholds(mut self, press: f32, release: f32) -> Self {
+        self.press = press;
+        self.release = release.min(press);
+        self
+    }
+
+    /// Set how far past the edge of a direction the stick has to go to
+    /// change direction, as a fraction of a direction's width (0.125 by
+    /// default).
+    pub fn margin(mut self, margin: f32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Get the current direction.
+    pub fn direction(&self) -> Option<Direction> {
+        self.current
+    }
+
+    /// Update with a new stick position, returning D-pad button events
+    /// (`Event::Up`, `Event::Down`, `Event::Left` and `Event::Right`) for
+    /// what changed.
+    pub fn update<S: Into<Stick>>(
+        &mut self,
+        stick: S,
+    ) -> impl Iterator<Item = Event> {
+        let stick = stick.into();
+        let old = self.current;
+        let threshold = if old.is_some() {
+            self.release
+        } else {
+            self.press
+        };
+
+        self.current = if stick.magnitude() < threshold {
+            None
+        } else {
+            match old {
+                Some(dir) if self.within(dir, stick.angle()) => Some(dir),
+                _ => {
+                    let sector = stick.sector(self.ways, 0.0).unwrap();
+                    Some(
+                        Direction::ALL
+                            [sector as usize * 8 / self.ways as usize],
+                    )
+                }
+            }
+        };
+
+        let new = self.current;
+        let has =
+            move |dir: Option<Direction>, btn| dir.is_some_and(|d| d.has(btn));
+        let btns: [(Btn, BtnEvent); 4] = [
+            (Btn::Up, Event::Up),
+            (Btn::Down, Event::Down),
+            (Btn::Left, Event::Left),
+            (Btn::Right, Event::Right),
+        ];
+        IntoIterator::into_iter(btns).filter_map(move |(btn, event)| {
+            let is = has(new, btn);
+            if has(old, btn) != is {
+                Some(event(is))
+            } else {
+                None
+            }
+        })
+    }
+
+    // Check if an angle is still within a direction, plus the margin.
+    fn within(&self, dir: Direction, angle: f32) -> bool {
+        let width = 2.0 * PI / self.ways as f32;
+        let center = dir.sector() as f32 * PI / 4.0;
+        let mut off = (angle - center).abs();
+        if off > PI {
+            off = 2.0 * PI - off;
+        }
+        off <= width * (0.5 + self.margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles() {
+        let up = Stick::from((0.0, -1.0));
+        assert_eq!(up.angle(), 0.0);
+        assert_eq!(up.magnitude(), 1.0);
+        assert!((Stick::from((1.0, 0.0)).angle() - PI / 2.0).abs() < 1e-6);
+        assert!((Stick::from((0.0, 1.0)).angle() - PI).abs() < 1e-6);
+        assert!((Stick::from((-1.0, 0.0)).angle() - PI * 1.5).abs() < 1e-6);
+        assert_eq!(Stick::from((1.0, 1.0)).magnitude(), 1.0);
+    }
+
+    #[test]
+    fn directions() {
+        // The sectors the typer example used to work out by hand.
+        let cases = [
+            ((0.0, -0.9), Direction::Up, Some(Direction::Up)),
+            ((0.7, -0.7), Direction::UpRight, None),
+            ((0.9, 0.1), Direction::Right, Some(Direction::Right)),
+            ((0.7, 0.7), Direction::DownRight, None),
+            ((0.0, 0.9), Direction::Down, Some(Direction::Down)),
+            ((-0.7, 0.7), Direction::DownLeft, None),
+            ((-0.9, 0.0), Direction::Left, Some(Direction::Left)),
+            ((-0.7, -0.7), Direction::UpLeft, None),
+        ];
+        for &(joy, eight, four) in cases.iter() {
+            let stick = Stick::from(joy);
+            assert_eq!(stick.direction8(0.5), Some(eight), "{:?}", joy);
+            if four.is_some() {
+                assert_eq!(stick.direction4(0.5), four, "{:?}", joy);
+            }
+        }
+        assert_eq!(
+            Stick::from((0.8, -0.6)).direction4(0.5),
+            Some(Direction::Right)
+        );
+        assert_eq!(Stick::from((0.3, 0.3)).direction8(0.5), None);
+    }
+
+    #[test]
+    fn sectors() {
+        let stick = |angle: f32| Stick::from((angle.sin(), -angle.cos()));
+        for slices in 1..=12u8 {
+            let width = 2.0 * PI / slices as f32;
+            for i in 0..slices {
+                let center = i as f32 * width;
+                assert_eq!(stick(center).sector(slices, 0.5), Some(i));
+                let edge = center + width * 0.49;
+                assert_eq!(stick(edge).sector(slices, 0.5), Some(i));
+            }
+        }
+        assert_eq!(Stick::default().sector(6, 0.1), None);
+        assert_eq!(stick(1.0).sector(0, 0.0), None);
+    }
+
+    #[test]
+    fn shapes() {
+        let close = |a: Stick, b: (f32, f32)| {
+            assert!((a.x - b.0).abs() < 1e-3, "{:?} {:?}", a, b);
+            assert!((a.y - b.1).abs() < 1e-3, "{:?} {:?}", a, b);
+        };
+        let corner = Stick::from((1.0, -1.0));
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert_eq!(corner.shaped(StickShape::Raw), corner);
+        close(corner.shaped(StickShape::Circle), (half, -half));
+        close(corner.shaped(StickShape::SquareToDisc), (half, -half));
+        // The edge of a square stays on the circle, and the center of it
+        // doesn't move.
+        let edge = Stick::from((1.0, 0.5)).shaped(StickShape::SquareToDisc);
+        assert!((edge.magnitude() - 1.0).abs() < 0.1);
+        close(
+            Stick::from((0.5, 0.0)).shaped(StickShape::SquareToDisc),
+            (0.5, 0.0),
+        );
+
+        // Notches stay put, and the edge between them reaches the circle.
+        let notch = Stick::from((half, half));
+        close(notch.shaped(StickShape::Octagon), (half, half));
+        close(
+            Stick::from((0.0, 1.0)).shaped(StickShape::Octagon),
+            (0.0, 1.0),
+        );
+        let angle = PI / 8.0;
+        let edge = (PI / 8.0).cos();
+        let between = Stick::from((edge * angle.sin(), -edge * angle.cos()));
+        let shaped = between.shaped(StickShape::Octagon);
+        assert!((shaped.magnitude() - 1.0).abs() < 1e-3);
+        assert!((shaped.angle() - angle).abs() < 1e-3);
+        close(
+            Stick::from((0.0, -0.5)).shaped(StickShape::Octagon),
+            (0.0, -0.5),
+        );
+    }
+
+    #[test]
+    fn dpad() {
+        let mut dpad = Dpad::eight();
+        let events =
+            |dpad: &mut Dpad, joy| dpad.update(joy).collect::<Vec<_>>();
+
+        assert_eq!(events(&mut dpad, (0.0, -0.45)), []);
+        assert_eq!(events(&mut dpad, (0.0, -0.6)), [Event::Up(true)]);
+        // Hysteresis on how far the stick is pushed.
+        assert_eq!(events(&mut dpad, (0.0, -0.45)), []);
+        assert_eq!(dpad.direction(), Some(Direction::Up));
+        // And on the angle: just past the edge of up stays up.
+        let angle = PI / 8.0 + 0.05;
+        assert_eq!(events(&mut dpad, (angle.sin(), -angle.cos())), []);
+        assert_eq!(events(&mut dpad, (0.7, -0.7)), [Event::Right(true)]);
+        assert_eq!(events(&mut dpad, (0.9, 0.0)), [Event::Up(false)]);
+        assert_eq!(
+            events(&mut dpad, (-0.9, 0.0)),
+            [Event::Left(true), Event::Right(false)]
+        );
+        assert_eq!(events(&mut dpad, (-0.3, 0.0)), [Event::Left(false)]);
+        assert_eq!(dpad.direction(), None);
+
+        let mut dpad = Dpad::four();
+        assert_eq!(events(&mut dpad, (0.7, -0.75)), [Event::Up(true)]);
+        assert_eq!(dpad.direction(), Some(Direction::Up));
+        assert_eq!(events(&mut dpad, (0.8, -0.6)), []);
+        assert_eq!(
+            events(&mut dpad, (0.9, -0.3)),
+            [Event::Up(false), Event::Right(true)]
+        );
+    }
+}
@@ -1,7 +1,17 @@
+use crate::Direction;
+
 /// An event on the "Standard Gamepad" from w3c shown below.
 ///
 /// ![Standard Gamepad](https://w3c.github.io/gamepad/standard_gamepad.svg)
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Event {
+    /// A controller was plugged in.
+    Connect,
+    /// A controller was unplugged.
+    Disconnect,
+    /// A controller performed a gesture added with `Port::gesture()`.
+    Gesture(u16),
+
     /// Bottom right cluster (A / Circle / Return / Right Click).
     Accept(bool),
     /// Bottom right cluster (B / X / Shift).
@@ -49,6 +59,21 @@ pub enum Event {
     /// Right Joystick Button (F)
     CameraButton(bool),
 
-    /// Home button (Target platform application close)
+    /// Home button (Target platform application close), like Xbox Guide or
+    /// PlayStation's PS button (`BTN_MODE`).  Sent when it's pressed.
     Exit,
+    /// Share button on Xbox Series controllers (`KEY_RECORD`).
+    Share(bool),
+    /// Back paddle on Xbox Elite controllers, 0 to 3: upper right, lower
+    /// right, upper left and lower left (`BTN_TRIGGER_HAPPY5` to `8`).
+    Paddle(u8, bool),
+
+    /// Any button changed (`BTN_*` or `KEY_*` code), when
+    /// `Port::raw_events()` is on.
+    Button(u16, bool),
+    /// Any axis moved (`ABS_*` code, scaled like `Device::axis()`), when
+    /// `Port::raw_events()` is on.
+    Axis(u8, f32),
+    /// A hat (0 to 3) changed direction, when `Port::raw_events()` is on.
+    Hat(u8, Option<Direction>),
 }
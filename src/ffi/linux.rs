@@ -1,7 +1,13 @@
-use smelling_salts::{Watcher, Device as AsyncDevice};
+use crate::backend::{Backend, RawInput};
+use crate::devices::{AbsInfo, DeviceInfo};
 
 use std::fs;
+use std::io;
 use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::task::{Context, Waker};
+use std::time::Duration;
 
 // use crate::devices::MAX_JS;
 
@@ -9,31 +15,100 @@ extern "C" {
     fn open(pathname: *const u8, flags: i32) -> i32;
     fn close(fd: i32) -> i32;
     fn fcntl(fd: i32, cmd: i32, v: i32) -> i32;
+    fn epoll_create1(flags: i32) -> i32;
+    fn epoll_ctl(epfd: i32, op: i32, fd: i32, event: *mut EpollEvent) -> i32;
+    fn epoll_wait(
+        epfd: i32,
+        events: *mut EpollEvent,
+        maxevents: i32,
+        timeout: i32,
+    ) -> i32;
+}
+
+const EPOLL_CLOEXEC: i32 = 0o2000000;
+const EPOLL_CTL_ADD: i32 = 1;
+const EPOLL_CTL_DEL: i32 = 2;
+const EPOLL_CTL_MOD: i32 = 3;
+const EPOLLIN: u32 = 0x001;
+const EPOLLONESHOT: u32 = 1 << 30;
+
+// The touchpad's click (`BTN_LEFT`).
+const BTN_LEFT: u16 = 0x110;
+
+// Token for the inotify file descriptor in the epoll set.
+const INOTIFY: u64 = u64::MAX;
+
+#[cfg_attr(target_arch = "x86_64", repr(C, packed))]
+#[cfg_attr(not(target_arch = "x86_64"), repr(C))]
+#[derive(Copy, Clone)]
+struct EpollEvent {
+    events: u32,
+    data: u64,
 }
 
 #[repr(C)]
 struct Device {
     name: [u8; 256 + 17],
-    async_device: AsyncDevice,
+    fd: i32,
+    // Id of the uploaded rumble effect, or -1 for none yet.
+    effect: i16,
+    // The touchpad's own evdev device (PlayStation controllers), or -1.
+    touchpad: i32,
+    // Whether events were dropped, and the state needs reading once the
+    // frame they were dropped from ends.
+    dropped: bool,
+}
+
+// A force feedback effect (`struct ff_effect`), with the rumble part of the
+// union (strong and weak magnitude) at the start of `u`.
+#[repr(C)]
+struct FfEffect {
+    type_: u16,
+    id: i16,
+    direction: u16,
+    trigger: [u16; 2],
+    replay: [u16; 2],
+    u: [u64; 4],
+}
+
+// A raw evdev event (`struct input_event`).
+#[repr(C)]
+struct InputEvent {
+    ev_time: [isize; 2],
+    ev_type: u16,
+    ev_code: u16,
+    ev_value: i32,
+}
+
+/// Something that has input ready to be read.
+enum Ready {
+    /// A controller was plugged in or removed.
+    Hotplug,
+    /// A controller (by index) has events.
+    Device(usize),
 }
 
 pub struct NativeManager {
-    // Inotify Device.
-    pub(crate) async_device: AsyncDevice,
+    // Inotify File Descriptor.
+    inotify: i32,
+    // Epoll File Descriptor, watching inotify and all controllers.
+    epoll: i32,
     // Controller File Descriptors.
     devices: Vec<Device>,
+    // Controllers found at startup, not yet reported to the `Port`.
+    found: Vec<usize>,
+    // Whether the epoll fd has been given to the reactor thread.
+    reactor: bool,
+    // Tokio's registration of the epoll file descriptor.
+    #[cfg(feature = "tokio")]
+    tokio: Option<tokio::io::unix::AsyncFd<EpollFd>>,
 }
 
 impl NativeManager {
     pub fn new() -> NativeManager {
         let inotify = inotify_new();
-        let watcher = Watcher::new().input();
-        let async_device = AsyncDevice::new(inotify, watcher);
-
-        let mut nm = NativeManager {
-            async_device,
-            devices: Vec::new(),
-        };
+        let mut nm = NativeManager::with_inotify(inotify);
+        epoll_add(nm.epoll, inotify, INOTIFY);
 
         // Look for joysticks immediately.
         let paths = fs::read_dir("/dev/input/by-id/");
@@ -65,71 +140,488 @@ impl NativeManager {
                 event.name[..slice_len]
                     .clone_from_slice(&path_str[..slice_len]);
 
-                inotify_read2(&mut nm, event);
+                if let Some((true, index)) = inotify_read2(&mut nm, event) {
+                    nm.found.push(index);
+                }
             }
         }
 
         nm
     }
 
-    pub fn get_id(&self, id: usize) -> (u32, bool) {
-        if id >= self.devices.len() {
-            (0, true)
-        } else {
-            let (a, b) = joystick_id(self.devices[id].async_device.fd());
+    // Open just the devices at `paths`, without watching for hotplugging.
+    pub fn with_paths<P: AsRef<Path>>(paths: &[P]) -> io::Result<Self> {
+        let mut nm = NativeManager::with_inotify(-1);
 
-            (a, b)
+        for path in paths {
+            let path = path.as_ref().as_os_str().to_string_lossy();
+            let mut name = [0; 256 + 17];
+            if path.len() >= name.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Device path is too long",
+                ));
+            }
+            name[..path.len()].copy_from_slice(path.as_bytes());
+            let id = joystick_add(&mut nm, name)?;
+            nm.found.push(id);
         }
+
+        Ok(nm)
     }
 
-    pub fn get_abs(&self, id: usize) -> (i32, i32, bool) {
+    fn with_inotify(inotify: i32) -> Self {
+        let epoll = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+        if epoll == -1 {
+            panic!("Couldn't create epoll!");
+        }
+
+        NativeManager {
+            inotify,
+            epoll,
+            devices: Vec::new(),
+            found: Vec::new(),
+            reactor: false,
+            #[cfg(feature = "tokio")]
+            tokio: None,
+        }
+    }
+
+    fn get_info(&self, id: usize) -> DeviceInfo {
         if id >= self.devices.len() {
-            (0, 0, true)
-        } else {
-            joystick_abs(self.devices[id].async_device.fd())
+            return DeviceInfo::default();
+        }
+
+        let fd = self.devices[id].fd;
+        let bits = joystick_abs_bits(fd);
+        let mut keys = joystick_key_bits(fd);
+        if self.devices[id].touchpad != -1 {
+            keys.insert(0, BTN_LEFT);
+        }
+
+        DeviceInfo {
+            hardware_id: joystick_id(fd).0,
+            name: joystick_name(fd),
+            uniq: joystick_uniq(fd),
+            abs: (0..0x40u8)
+                .filter(|code| bits & (1 << code) != 0)
+                .filter_map(|code| Some((code, joystick_abs(fd, code)?)))
+                .collect(),
+            keys,
+            path: {
+                let name = &self.devices[id].name;
+                let len = name.iter().position(|c| *c == b'\0').unwrap_or(0);
+                String::from_utf8_lossy(&name[..len]).to_string()
+            },
         }
     }
 
-    pub fn get_fd(&self, id: usize) -> (i32, bool, bool) {
-        let (_, unplug) = self.get_id(id);
+    fn disconnect(&mut self, id: usize) {
+        let fd = self.devices[id].fd;
+        let touchpad = self.devices[id].touchpad;
 
-        (
-            self.devices[id].async_device.fd(),
-            unplug,
-            self.devices[id].name[0] == b'\0',
-        )
+        epoll_del(self.epoll, fd);
+        joystick_drop(fd);
+        if touchpad != -1 {
+            epoll_del(self.epoll, touchpad);
+            joystick_drop(touchpad);
+        }
+        self.devices[id].name[0] = b'\0';
     }
 
-    pub fn num_plugged_in(&self) -> usize {
-        self.devices.len()
+    // Get everything that has input ready, waiting up to `timeout`
+    // milliseconds (`-1` waits forever).
+    fn ready(&self, timeout: i32) -> Vec<Ready> {
+        let mut events = [EpollEvent { events: 0, data: 0 }; 16];
+        let n =
+            unsafe { epoll_wait(self.epoll, events.as_mut_ptr(), 16, timeout) };
+
+        // Interrupted by a signal (or nothing ready).
+        if n <= 0 {
+            return Vec::new();
+        }
+
+        events[..n as usize]
+            .iter()
+            .map(|event| match event.data {
+                INOTIFY => Ready::Hotplug,
+                index => Ready::Device(index as usize),
+            })
+            .collect()
     }
 
-    pub fn disconnect(&mut self, fd: i32) -> usize {
-        for i in 0..self.devices.len() {
-            if self.devices[i].async_device.fd() == fd {
-                self.async_device.old();
-                joystick_drop(fd);
-                self.devices[i].name[0] = b'\0';
-                return i;
+    // Read all events from a controller, noticing if it's been unplugged.
+    fn read_device(&mut self, id: usize, input: &mut Vec<RawInput>) {
+        if id >= self.devices.len() || self.devices[id].name[0] == b'\0' {
+            return;
+        }
+
+        let fd = self.devices[id].fd;
+        if joystick_id(fd).1 {
+            input.push(RawInput::Disconnect(id));
+            self.disconnect(id);
+            return;
+        }
+
+        while let Some(ev) = joystick_read(fd) {
+            input.push(RawInput::Event(
+                id,
+                ev.ev_type,
+                ev.ev_code,
+                ev.ev_value,
+            ));
+            match (ev.ev_type, ev.ev_code) {
+                // SYN_DROPPED: the `Port` throws away the rest of the frame.
+                (0x00, 3) => self.devices[id].dropped = true,
+                // Then gets what was lost from the device's current state.
+                (0x00, 0) if self.devices[id].dropped => {
+                    self.devices[id].dropped = false;
+                    joystick_sync(fd, id, input);
+                }
+                _ => {}
             }
         }
 
-        panic!("There was no fd of {}", fd);
+        // Only the touchpad's click, in its own frame.  Its touches would
+        // clash with the sticks' axes.
+        let touchpad = self.devices[id].touchpad;
+        if touchpad == -1 {
+            return;
+        }
+        while let Some(ev) = joystick_read(touchpad) {
+            if ev.ev_type == 0x01 && ev.ev_code == BTN_LEFT {
+                input.push(RawInput::Event(id, 0x01, BTN_LEFT, ev.ev_value));
+                input.push(RawInput::Event(id, 0x00, 0, 0));
+            }
+        }
+    }
+}
+
+impl Backend for NativeManager {
+    fn write(
+        &mut self,
+        id: usize,
+        ev_type: u16,
+        code: u16,
+        value: i32,
+    ) -> io::Result<()> {
+        extern "C" {
+            fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+        }
+
+        if id >= self.devices.len() || self.devices[id].name[0] == b'\0' {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Device is unplugged",
+            ));
+        }
+
+        let ev = InputEvent {
+            ev_time: [0; 2],
+            ev_type,
+            ev_code: code,
+            ev_value: value,
+        };
+        let size = mem::size_of::<InputEvent>();
+        let ptr = &ev as *const InputEvent as *const u8;
+        if unsafe { write(self.devices[id].fd, ptr, size) } != size as isize {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn rumble(
+        &mut self,
+        id: usize,
+        strong: u16,
+        weak: u16,
+        duration: Duration,
+    ) -> io::Result<()> {
+        extern "C" {
+            fn ioctl(fd: i32, request: usize, ...) -> i32;
+        }
+
+        if id >= self.devices.len() || self.devices[id].name[0] == b'\0' {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Device is unplugged",
+            ));
+        }
+
+        let device = &mut self.devices[id];
+        let length = duration.as_millis().min(u16::MAX.into()) as u16;
+        let mut effect = FfEffect {
+            type_: 0x50, // FF_RUMBLE
+            id: device.effect,
+            direction: 0,
+            trigger: [0; 2],
+            replay: [length, 0],
+            u: [u64::from(strong) | u64::from(weak) << 16, 0, 0, 0],
+        };
+        // EVIOCSFF: _IOW('E', 0x80, struct ff_effect), which reuses the
+        // effect if it's already been uploaded.
+        let request = 0x_4000_4580 | mem::size_of::<FfEffect>() << 16;
+        if unsafe { ioctl(device.fd, request, &mut effect as *mut FfEffect) }
+            == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+        device.effect = effect.id;
+
+        // Play it, or stop it if both motors are off.
+        let play = (strong != 0 || weak != 0) as i32;
+        self.write(id, 0x15, effect.id as u16, play)
+    }
+
+    fn set_light(&mut self, id: usize, r: u8, g: u8, b: u8) -> io::Result<()> {
+        if id >= self.devices.len() || self.devices[id].name[0] == b'\0' {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Device is unplugged",
+            ));
+        }
+
+        // The multicolor LED (such as `input12:rgb:indicator`).
+        let leds = joystick_sysfs(&self.devices[id].name)
+            .and_then(|hid| fs::read_dir(hid.join("leds")).ok())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|led| led.path())
+            .find(|led| {
+                led.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.contains(":rgb:"))
+            });
+        let led = if let Some(led) = leds {
+            led
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Device has no multicolor LED",
+            ));
+        };
+
+        fs::write(led.join("multi_intensity"), format!("{} {} {}\n", r, g, b))?;
+        let max = fs::read_to_string(led.join("max_brightness"))?;
+        fs::write(led.join("brightness"), max)
+    }
+
+    fn poll(&mut self, timeout: Option<Duration>, input: &mut Vec<RawInput>) {
+        // Report controllers found at startup right away.
+        let timeout = if !self.found.is_empty() {
+            0
+        } else if let Some(timeout) = timeout {
+            // Round up, so as to not spin when less than 1ms is left.
+            timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+        } else {
+            -1
+        };
+        for id in mem::take(&mut self.found) {
+            input.push(RawInput::Connect(id, self.get_info(id)));
+        }
+
+        for ready in self.ready(timeout) {
+            match ready {
+                Ready::Hotplug => {
+                    for id in inotify_read(self) {
+                        input.push(RawInput::Connect(id, self.get_info(id)));
+                    }
+                }
+                Ready::Device(id) => self.read_device(id, input),
+            }
+        }
+    }
+
+    fn wake_on_input(&mut self, cx: &mut Context<'_>) -> bool {
+        if !self.found.is_empty() {
+            return true;
+        }
+
+        #[cfg(feature = "tokio")]
+        {
+            use std::task::Poll;
+
+            if tokio::runtime::Handle::try_current().is_ok() {
+                if self.tokio.is_none() {
+                    // The epoll fd is only closed after the `AsyncFd` is
+                    // dropped (see `Drop`).
+                    self.tokio = unsafe {
+                        tokio::io::unix::AsyncFd::register_with_interest(
+                            EpollFd(self.epoll),
+                            tokio::io::Interest::READABLE,
+                        )
+                    }
+                    .ok();
+                }
+                if let Some(ref async_fd) = self.tokio {
+                    match async_fd.poll_read_ready(cx) {
+                        Poll::Ready(Ok(mut guard)) => {
+                            guard.clear_ready();
+                            return true;
+                        }
+                        Poll::Ready(Err(_)) => {}
+                        Poll::Pending => return false,
+                    }
+                }
+            }
+        }
+
+        self.reactor = true;
+        reactor().register(self.epoll, cx.waker());
+        false
     }
 }
 impl Drop for NativeManager {
     fn drop(&mut self) {
-        while let Some(device) = self.devices.pop() {
-            self.disconnect(device.async_device.fd());
+        #[cfg(feature = "tokio")]
+        {
+            self.tokio = None;
+        }
+        if self.reactor {
+            reactor().unregister(self.epoll);
+        }
+
+        for id in 0..self.devices.len() {
+            if self.devices[id].name[0] != b'\0' {
+                self.disconnect(id);
+            }
         }
         unsafe {
-            let fd = self.async_device.fd();
-            self.async_device.old();
-            close(fd);
+            if self.inotify != -1 {
+                close(self.inotify);
+            }
+            close(self.epoll);
         }
     }
 }
 
+// The epoll file descriptor, for registering with tokio.
+#[cfg(feature = "tokio")]
+struct EpollFd(i32);
+
+#[cfg(feature = "tokio")]
+impl std::os::unix::io::AsRawFd for EpollFd {
+    fn as_raw_fd(&self) -> i32 {
+        self.0
+    }
+}
+
+// A thread that wakes tasks when the epoll file descriptor of their `Port`
+// becomes readable.  Works with any async executor.
+struct Reactor {
+    // Epoll File Descriptor, watching the epoll fd of each `Port`.
+    epoll: i32,
+    // Tasks waiting on each `Port`'s epoll fd.
+    wakers: Mutex<Vec<(i32, Waker)>>,
+}
+
+impl Reactor {
+    fn register(&self, fd: i32, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+
+        if let Some(slot) = wakers.iter_mut().find(|(f, _)| *f == fd) {
+            slot.1 = waker.clone();
+        } else {
+            wakers.push((fd, waker.clone()));
+        }
+
+        // Re-arm (one-shot), adding if this is the first time.
+        let mut event = EpollEvent {
+            events: EPOLLIN | EPOLLONESHOT,
+            data: fd as u64,
+        };
+        if unsafe { epoll_ctl(self.epoll, EPOLL_CTL_MOD, fd, &mut event) } == -1
+        {
+            let mut event = EpollEvent {
+                events: EPOLLIN | EPOLLONESHOT,
+                data: fd as u64,
+            };
+            unsafe { epoll_ctl(self.epoll, EPOLL_CTL_ADD, fd, &mut event) };
+        }
+    }
+
+    fn unregister(&self, fd: i32) {
+        let mut wakers = self.wakers.lock().unwrap();
+
+        wakers.retain(|(f, _)| *f != fd);
+        epoll_del(self.epoll, fd);
+    }
+
+    fn run(&self) -> ! {
+        let mut events = [EpollEvent { events: 0, data: 0 }; 16];
+
+        loop {
+            let n =
+                unsafe { epoll_wait(self.epoll, events.as_mut_ptr(), 16, -1) };
+
+            // Interrupted by a signal.
+            if n <= 0 {
+                continue;
+            }
+
+            let mut wakers = self.wakers.lock().unwrap();
+            for event in &events[..n as usize] {
+                let fd = event.data as i32;
+
+                if let Some(i) = wakers.iter().position(|(f, _)| *f == fd) {
+                    wakers.swap_remove(i).1.wake();
+                }
+            }
+        }
+    }
+}
+
+// Get the reactor, starting its thread the first time.
+fn reactor() -> &'static Reactor {
+    static REACTOR: OnceLock<Reactor> = OnceLock::new();
+
+    REACTOR.get_or_init(|| {
+        let epoll = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+        if epoll == -1 {
+            panic!("Couldn't create epoll!");
+        }
+
+        std::thread::Builder::new()
+            .name("stick-reactor".to_string())
+            .spawn(|| reactor().run())
+            .expect("Couldn't spawn reactor thread!");
+
+        Reactor {
+            epoll,
+            wakers: Mutex::new(Vec::new()),
+        }
+    })
+}
+
+fn epoll_add(epoll: i32, fd: i32, token: u64) {
+    let mut event = EpollEvent {
+        events: EPOLLIN,
+        data: token,
+    };
+
+    if unsafe { epoll_ctl(epoll, EPOLL_CTL_ADD, fd, &mut event) } == -1 {
+        panic!("Couldn't watch file descriptor with epoll!");
+    }
+}
+
+fn epoll_del(epoll: i32, fd: i32) {
+    let mut event = EpollEvent { events: 0, data: 0 };
+
+    unsafe { epoll_ctl(epoll, EPOLL_CTL_DEL, fd, &mut event) };
+}
+
+// Open a joystick, for writing too (force feedback and LEDs) if allowed.
+fn joystick_open(path: &[u8]) -> i32 {
+    // O_RDWR
+    let fd = unsafe { open(path.as_ptr(), 0x2) };
+    if fd != -1 {
+        return fd;
+    }
+    // O_RDONLY
+    unsafe { open(path.as_ptr(), 0x0) }
+}
+
 // Set up file descriptor for asynchronous reading.
 fn joystick_async(fd: i32) {
     let error = unsafe { fcntl(fd, 0x4, 0x800) } == -1;
@@ -139,46 +631,180 @@ fn joystick_async(fd: i32) {
     }
 }
 
+// Read one event, or `None` if there aren't any ready.
+fn joystick_read(fd: i32) -> Option<InputEvent> {
+    extern "C" {
+        fn read(fd: i32, buf: *mut u8, count: usize) -> isize;
+    }
+
+    let mut ev = mem::MaybeUninit::<InputEvent>::uninit();
+    let size = mem::size_of::<InputEvent>();
+    let bytes = unsafe { read(fd, ev.as_mut_ptr() as *mut u8, size) };
+    if bytes != size as isize {
+        return None;
+    }
+    Some(unsafe { ev.assume_init() })
+}
+
+// Get the sysfs directory of the HID device behind a joystick's evdev path,
+// which has its LEDs and its other input devices.
+fn joystick_sysfs(path: &[u8]) -> Option<PathBuf> {
+    let len = path.iter().position(|c| *c == b'\0').unwrap_or(path.len());
+    let path = String::from_utf8_lossy(&path[..len]).to_string();
+    // `/dev/input/by-id/...-event-joystick` links to `/dev/input/eventN`.
+    let event = fs::canonicalize(path).ok()?;
+    let event = event.file_name()?.to_str()?;
+    Some(PathBuf::from(format!(
+        "/sys/class/input/{}/device/device",
+        event
+    )))
+}
+
+// Open the touchpad that's a separate evdev device of the same controller,
+// or -1 if there isn't one.
+fn touchpad_open(path: &[u8]) -> i32 {
+    let inputs = joystick_sysfs(path)
+        .and_then(|hid| fs::read_dir(hid.join("input")).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|input| input.path());
+    for input in inputs {
+        let name = fs::read_to_string(input.join("name")).unwrap_or_default();
+        if !name.trim_end().ends_with("Touchpad") {
+            continue;
+        }
+        let events = fs::read_dir(&input).into_iter().flatten().flatten();
+        for event in events {
+            let event = event.file_name();
+            let event = event.to_string_lossy();
+            if event.starts_with("event") {
+                let path = format!("/dev/input/{}\0", event);
+                let fd = unsafe { open(path.as_ptr(), 0x0) };
+                if fd != -1 {
+                    joystick_async(fd);
+                }
+                return fd;
+            }
+        }
+    }
+    -1
+}
+
 // Get the joystick id.
 fn joystick_id(fd: i32) -> (u32, bool) {
     let mut a = [0u16; 4];
 
     extern "C" {
-        fn ioctl(fd: i32, request: usize, v: *mut u16) -> i32;
+        fn ioctl(fd: i32, request: usize, ...) -> i32;
     }
 
-    if unsafe { ioctl(fd, 0x_8008_4502, &mut a[0]) } == -1 {
+    if unsafe { ioctl(fd, 0x_8008_4502, &mut a[0] as *mut u16) } == -1 {
         return (0, true);
     }
 
     (((u32::from(a[1])) << 16) | (u32::from(a[2])), false)
 }
 
-fn joystick_abs(fd: i32) -> (i32, i32, bool) {
-    #[derive(Debug)]
-    #[repr(C)]
-    struct AbsInfo {
-        value: i32,
-        minimum: i32,
-        maximum: i32,
-        fuzz: i32,
-        flat: i32,
-        resolution: i32,
+// Get which absolute axes the joystick has (EVIOCGBIT for EV_ABS).
+fn joystick_abs_bits(fd: i32) -> u64 {
+    extern "C" {
+        fn ioctl(fd: i32, request: usize, ...) -> i32;
+    }
+
+    let mut bits = 0u64;
+    if unsafe { ioctl(fd, 0x_8008_4523, &mut bits as *mut u64) } == -1 {
+        return 0;
+    }
+
+    bits
+}
+
+// Get which buttons and keys the joystick has (EVIOCGBIT(EV_KEY)).
+fn joystick_key_bits(fd: i32) -> Vec<u16> {
+    extern "C" {
+        fn ioctl(fd: i32, request: usize, ...) -> i32;
+    }
+
+    // KEY_CNT bits.
+    let mut bits = [0u8; 0x300 / 8];
+    if unsafe { ioctl(fd, 0x_8060_4521, bits.as_mut_ptr()) } == -1 {
+        return Vec::new();
     }
 
+    (0..0x300u16)
+        .filter(|code| bits[*code as usize / 8] & (1 << (code % 8)) != 0)
+        .collect()
+}
+
+// Get the range of an absolute axis (EVIOCGABS).
+fn joystick_abs(fd: i32, axis: u8) -> Option<AbsInfo> {
     extern "C" {
-        fn ioctl(fd: i32, request: usize, v: *mut AbsInfo) -> i32;
+        fn ioctl(fd: i32, request: usize, ...) -> i32;
     }
 
-    let mut a = mem::MaybeUninit::uninit();
-    let a = unsafe {
-        if ioctl(fd, 0x_8018_4540, a.as_mut_ptr()) == -1 {
-            return (0, 0, true);
+    let mut a = mem::MaybeUninit::<AbsInfo>::uninit();
+    unsafe {
+        if ioctl(fd, 0x_8018_4540 + axis as usize, a.as_mut_ptr()) == -1 {
+            return None;
         }
-        a.assume_init()
-    };
+        Some(a.assume_init())
+    }
+}
+
+// Get the state of all buttons (EVIOCGKEY) and axes (EVIOCGABS), as a frame
+// of events.
+fn joystick_sync(fd: i32, id: usize, input: &mut Vec<RawInput>) {
+    extern "C" {
+        fn ioctl(fd: i32, request: usize, ...) -> i32;
+    }
 
-    (a.minimum, a.maximum, false)
+    // KEY_CNT bits.
+    let mut held = [0u8; 0x300 / 8];
+    if unsafe { ioctl(fd, 0x_8060_4518, held.as_mut_ptr()) } == -1 {
+        return;
+    }
+    for code in joystick_key_bits(fd) {
+        let value = held[code as usize / 8] >> (code % 8) & 1;
+        input.push(RawInput::Event(id, 0x01, code, value.into()));
+    }
+    let bits = joystick_abs_bits(fd);
+    for code in (0..0x40u8).filter(|code| bits & (1 << code) != 0) {
+        if let Some(abs) = joystick_abs(fd, code) {
+            input.push(RawInput::Event(id, 0x03, code.into(), abs.value));
+        }
+    }
+    input.push(RawInput::Event(id, 0x00, 0, 0));
+}
+
+// Get the name the joystick reports (EVIOCGNAME).
+fn joystick_name(fd: i32) -> String {
+    extern "C" {
+        fn ioctl(fd: i32, request: usize, ...) -> i32;
+    }
+
+    let mut name = [0u8; 256];
+    if unsafe { ioctl(fd, 0x_8100_4506, name.as_mut_ptr()) } == -1 {
+        return "Unknown".to_string();
+    }
+
+    let len = name.iter().position(|c| *c == b'\0').unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..len]).to_string()
+}
+
+// Get the unique ID of the joystick (`EVIOCGUNIQ`), if it has one.
+fn joystick_uniq(fd: i32) -> String {
+    extern "C" {
+        fn ioctl(fd: i32, request: usize, ...) -> i32;
+    }
+
+    let mut uniq = [0u8; 256];
+    if unsafe { ioctl(fd, 0x_8100_4508, uniq.as_mut_ptr()) } == -1 {
+        return String::new();
+    }
+
+    let len = uniq.iter().position(|c| *c == b'\0').unwrap_or(uniq.len());
+    String::from_utf8_lossy(&uniq[..len]).to_string()
 }
 
 // Disconnect the joystick.
@@ -190,11 +816,12 @@ fn joystick_drop(fd: i32) {
 
 fn inotify_new() -> i32 {
     extern "C" {
-        fn inotify_init() -> i32;
+        fn inotify_init1(flags: i32) -> i32;
         fn inotify_add_watch(fd: i32, pathname: *const u8, mask: u32) -> i32;
     }
 
-    let fd = unsafe { inotify_init() };
+    // Non-blocking, close-on-exec.
+    let fd = unsafe { inotify_init1(0x800 | 0o2000000) };
 
     if fd == -1 {
         panic!("Couldn't create inotify (1)!");
@@ -254,46 +881,115 @@ fn inotify_read2(port: &mut NativeManager, ev: Event) -> Option<(bool, usize)> {
     }
 
     let namer = String::from_utf8_lossy(&name[0..length]);
-    let mut fd = unsafe { open(name.as_ptr() as *const _, 0) };
     if !namer.ends_with("-event-joystick") || ev.mask != 0x0000_0100 {
         return None;
     }
 
+    let id = joystick_add(port, name)
+        .or_else(|_| {
+            // Avoid race condition
+            std::thread::sleep(std::time::Duration::from_millis(16));
+            joystick_add(port, name)
+        })
+        .ok()?;
+    Some((true, id))
+}
+
+// Open the evdev device at a path (NUL-terminated), returning its index.
+fn joystick_add(
+    port: &mut NativeManager,
+    name: [u8; 256 + 17],
+) -> io::Result<usize> {
+    let fd = joystick_open(&name);
     if fd == -1 {
-        // Avoid race condition
-        std::thread::sleep(std::time::Duration::from_millis(16));
-        fd = unsafe { open(name.as_ptr() as *const _, 0) };
-        if fd == -1 {
-            return None;
-        }
+        return Err(io::Error::last_os_error());
+    }
+    if joystick_id(fd).1 {
+        joystick_drop(fd);
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Not an evdev device",
+        ));
     }
 
     joystick_async(fd);
-    let async_device = AsyncDevice::new(fd, Watcher::new().input());
-    let device = Device { name, async_device };
+    // PlayStation controllers with a touchpad that clicks.
+    let touchpad = if crate::playstation::playstation(joystick_id(fd).0) {
+        touchpad_open(&name)
+    } else {
+        -1
+    };
+    let device = Device {
+        name,
+        fd,
+        effect: -1,
+        touchpad,
+        dropped: false,
+    };
 
-    for i in 0..port.devices.len() {
-        if port.devices[i].name[0] == b'\0' {
-            port.devices[i] = device;
-            return Some((true, i));
-        }
+    let id = port
+        .devices
+        .iter()
+        .position(|device| device.name[0] == b'\0')
+        .unwrap_or(port.devices.len());
+    epoll_add(port.epoll, fd, id as u64);
+    if touchpad != -1 {
+        epoll_add(port.epoll, touchpad, id as u64);
     }
-
-    port.devices.push(device);
-    Some((true, port.devices.len() - 1))
+    if id == port.devices.len() {
+        port.devices.push(device);
+    } else {
+        port.devices[id] = device;
+    }
+    Ok(id)
 }
 
-// Read joystick add or remove event.
-pub(crate) fn inotify_read(port: &mut NativeManager) -> Option<(bool, usize)> {
+// Read joystick add or remove events, returning the indices of added
+// joysticks.  Removal is noticed when reading from the joystick fails.
+fn inotify_read(port: &mut NativeManager) -> Vec<usize> {
     extern "C" {
-        fn read(fd: i32, buf: *mut Event, count: usize) -> isize;
+        fn read(fd: i32, buf: *mut u8, count: usize) -> isize;
     }
 
-    let mut ev = mem::MaybeUninit::uninit();
-    let ev = unsafe {
-        read(port.async_device.fd(), ev.as_mut_ptr(), mem::size_of::<Event>());
-        ev.assume_init()
-    };
+    let header = mem::size_of::<Event>() - 256;
+    let mut buf = [0u8; 4096];
+    let mut added = Vec::new();
+
+    loop {
+        let len = unsafe { read(port.inotify, buf.as_mut_ptr(), buf.len()) };
+        if len <= 0 {
+            break;
+        }
+
+        // Each event is a header followed by a variable length name.
+        let mut offset = 0;
+        while offset + header <= len as usize {
+            let mut ev = Event {
+                wd: 0,
+                mask: 0,
+                cookie: 0,
+                len: 0,
+                name: [0; 256],
+            };
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    buf[offset..].as_ptr(),
+                    &mut ev as *mut Event as *mut u8,
+                    header,
+                );
+            }
+            let name_len = (ev.len as usize).min(255);
+            let name_start = offset + header;
+            let name_end = (name_start + name_len).min(len as usize);
+            ev.name[..name_end - name_start]
+                .copy_from_slice(&buf[name_start..name_end]);
+            offset = name_start + ev.len as usize;
+
+            if let Some((true, index)) = inotify_read2(port, ev) {
+                added.push(index);
+            }
+        }
+    }
 
-    inotify_read2(port, ev)
+    added
 }
@@ -0,0 +1,392 @@
+use crate::{Btn, DeviceState};
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// How far the main stick has to be pushed to count as a D-pad direction.
+const STICK_THRESHOLD: f32 = 0.5;
+// How many button changes to remember for combos.
+const HISTORY: usize = 32;
+
+/// A pattern of button presses to recognize, added with `Port::gesture()`.
+/// When a controller performs it, the `Port` sends `Event::Gesture` with the
+/// gesture's id.
+///
+/// The main joystick counts as the D-pad (`Btn::Up`, `Btn::Down`,
+/// `Btn::Left` and `Btn::Right`), so motion inputs work with either.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gesture {
+    /// A button held down for at least this long ("hold Back to quit").
+    Hold(Btn, Duration),
+    /// A button pressed this many times, each press within the window of the
+    /// last ("double-tap to dash").
+    Taps(Btn, u8, Duration),
+    /// Buttons held at the same time, in any order.  Recognized when the
+    /// last one is pressed.
+    Chord(Vec<Btn>),
+    /// Steps of buttons, in order, each step within the leniency window of
+    /// the last.  Every button in a step must be held, so a diagonal is
+    /// `[Btn::Down, Btn::Right]`.  Recognized when the last step is reached.
+    Combo(Vec<Vec<Btn>>, Duration),
+}
+
+// Gesture recognition state for one controller.
+#[derive(Debug, Default)]
+pub(crate) struct Recognizer {
+    // Buttons (and stick directions) held.
+    held: u32,
+    // When the buttons held changed, and what to.
+    history: VecDeque<(Instant, u32)>,
+    // State for each gesture.
+    gestures: Vec<Progress>,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Progress {
+    // Number of taps so far.
+    taps: u8,
+    // When the button was last pressed (holds and taps).
+    pressed: Option<Instant>,
+    // When the gesture was last recognized (combos).
+    fired: Option<Instant>,
+}
+
+impl Recognizer {
+    // Start over, for a newly plugged in controller.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // Look for gestures in a new frame.
+    pub(crate) fn update(
+        &mut self,
+        gestures: &[Gesture],
+        now: Instant,
+        state: &DeviceState,
+        out: &mut Vec<u16>,
+    ) {
+        self.gestures.resize(gestures.len(), Progress::default());
+
+        let held = mask(state);
+        let old = self.held;
+        if held == old {
+            return;
+        }
+        let pressed = held & !old;
+        self.held = held;
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((now, held));
+
+        for (id, gesture) in gestures.iter().enumerate() {
+            let progress = &mut self.gestures[id];
+            let found = match *gesture {
+                Gesture::Hold(btn, _) => {
+                    if pressed & bit(btn) != 0 {
+                        progress.pressed = Some(now);
+                    } else if held & bit(btn) == 0 {
+                        progress.pressed = None;
+                    }
+                    false
+                }
+                Gesture::Taps(btn, count, window) => {
+                    if pressed & bit(btn) == 0 {
+                        continue;
+                    }
+                    progress.taps = match progress.pressed {
+                        Some(last) if now - last <= window => progress.taps + 1,
+                        _ => 1,
+                    };
+                    progress.pressed = Some(now);
+                    if progress.taps >= count {
+                        progress.taps = 0;
+                        progress.pressed = None;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                Gesture::Chord(ref btns) => {
+                    let all = btns.iter().fold(0, |m, b| m | bit(*b));
+                    all != 0 && held & all == all && old & all != all
+                }
+                Gesture::Combo(ref steps, leniency) => {
+                    let after = progress.fired;
+                    if combo(&self.history, steps, leniency, after) {
+                        progress.fired = Some(now);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            };
+            if found {
+                out.push(id as u16);
+            }
+        }
+    }
+
+    // Look for gestures that depend on time passing (holds).
+    pub(crate) fn tick(
+        &mut self,
+        gestures: &[Gesture],
+        now: Instant,
+        out: &mut Vec<u16>,
+    ) {
+        for (id, (gesture, progress)) in
+            gestures.iter().zip(self.gestures.iter_mut()).enumerate()
+        {
+            if let (Gesture::Hold(_, time), Some(pressed)) =
+                (gesture, progress.pressed)
+            {
+                if now - pressed >= *time {
+                    progress.pressed = None;
+                    out.push(id as u16);
+                }
+            }
+        }
+    }
+
+    // When `tick()` should be called next, if ever.
+    pub(crate) fn deadline(&self, gestures: &[Gesture]) -> Option<Instant> {
+        gestures
+            .iter()
+            .zip(self.gestures.iter())
+            .filter_map(|(gesture, progress)| match gesture {
+                Gesture::Hold(_, time) => Some(progress.pressed? + *time),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+// Check if a combo was just completed, looking back through the history
+// from the latest change.  Steps can't reuse changes from before `after`.
+fn combo(
+    history: &VecDeque<(Instant, u32)>,
+    steps: &[Vec<Btn>],
+    leniency: Duration,
+    after: Option<Instant>,
+) -> bool {
+    let step = |i: usize| steps[i].iter().fold(0, |m, b| m | bit(*b));
+    if steps.is_empty() {
+        return false;
+    }
+    let last = steps.len() - 1;
+
+    // The last step has to be newly reached.
+    let mut index = history.len() - 1;
+    let (mut time, held) = history[index];
+    let want = step(last);
+    if want == 0 || held & want != want {
+        return false;
+    }
+    if index > 0 && history[index - 1].1 & want == want {
+        return false;
+    }
+
+    for i in (0..last).rev() {
+        let want = step(i);
+        loop {
+            if index == 0 {
+                return false;
+            }
+            index -= 1;
+            let (when, held) = history[index];
+            if time - when > leniency || Some(when) <= after {
+                return false;
+            }
+            if want != 0 && held & want == want {
+                time = when;
+                break;
+            }
+        }
+    }
+    true
+}
+
+// The buttons held, with the main stick as the D-pad.
+fn mask(state: &DeviceState) -> u32 {
+    let mut mask = state.btns;
+    if let Some((x, y)) = state.joy() {
+        if x <= -STICK_THRESHOLD {
+            mask |= bit(Btn::Left);
+        }
+        if x >= STICK_THRESHOLD {
+            mask |= bit(Btn::Right);
+        }
+        if y <= -STICK_THRESHOLD {
+            mask |= bit(Btn::Up);
+        }
+        if y >= STICK_THRESHOLD {
+            mask |= bit(Btn::Down);
+        }
+    }
+    mask
+}
+
+fn bit(btn: Btn) -> u32 {
+    1 << btn as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{drain, mock_port};
+    use crate::{DeviceInfo, Event};
+
+    fn state(btns: &[Btn], joy: (f32, f32)) -> DeviceState {
+        DeviceState {
+            btns: btns.iter().fold(0, |m, b| m | bit(*b)),
+            joy,
+            ..Default::default()
+        }
+    }
+
+    // Feed frames at millisecond offsets, returning recognized gestures.
+    fn run(
+        gestures: &[Gesture],
+        frames: &[(u64, &[Btn], (f32, f32))],
+    ) -> Vec<(u64, u16)> {
+        let start = Instant::now();
+        let mut recognizer = Recognizer::default();
+        let mut found = Vec::new();
+        for &(ms, btns, joy) in frames {
+            let now = start + Duration::from_millis(ms);
+            let mut out = Vec::new();
+            recognizer.tick(gestures, now, &mut out);
+            recognizer.update(gestures, now, &state(btns, joy), &mut out);
+            found.extend(out.into_iter().map(|id| (ms, id)));
+        }
+        found
+    }
+
+    const NONE: (f32, f32) = (0.0, 0.0);
+
+    #[test]
+    fn hold() {
+        let gestures = [Gesture::Hold(Btn::F, Duration::from_secs(2))];
+        let frames: &[(u64, &[Btn], _)] = &[
+            (0, &[Btn::F], NONE),
+            (1000, &[], NONE),
+            (1500, &[Btn::F], NONE),
+            (3400, &[Btn::F, Btn::A], NONE),
+            (3600, &[Btn::F], NONE),
+            (6000, &[], NONE),
+        ];
+        assert_eq!(run(&gestures, frames), [(3600, 0)]);
+
+        let start = Instant::now();
+        let mut recognizer = Recognizer::default();
+        let mut out = Vec::new();
+        recognizer.update(&gestures, start, &state(&[Btn::F], NONE), &mut out);
+        let deadline = recognizer.deadline(&gestures);
+        assert_eq!(deadline, Some(start + Duration::from_secs(2)));
+        recognizer.tick(&gestures, deadline.unwrap(), &mut out);
+        assert_eq!(out, [0]);
+        assert_eq!(recognizer.deadline(&gestures), None);
+    }
+
+    #[test]
+    fn taps() {
+        let gestures = [
+            Gesture::Taps(Btn::Right, 2, Duration::from_millis(250)),
+            Gesture::Taps(Btn::A, 3, Duration::from_millis(250)),
+        ];
+        let frames: &[(u64, &[Btn], _)] = &[
+            // Too slow.
+            (0, &[Btn::Right], NONE),
+            (100, &[], NONE),
+            (400, &[Btn::Right], NONE),
+            (450, &[], NONE),
+            // Fast enough, with the stick.
+            (600, &[], (0.9, 0.0)),
+            (700, &[], NONE),
+            // Doesn't count as the start of another double tap.
+            (800, &[Btn::Right], NONE),
+            (850, &[], NONE),
+            (2000, &[Btn::A], NONE),
+            (2100, &[], NONE),
+            (2200, &[Btn::A], NONE),
+            (2300, &[], NONE),
+            (2400, &[Btn::A], NONE),
+        ];
+        assert_eq!(run(&gestures, frames), [(600, 0), (2400, 1)]);
+    }
+
+    #[test]
+    fn chord() {
+        let gestures = [Gesture::Chord(vec![Btn::F, Btn::E])];
+        let frames: &[(u64, &[Btn], _)] = &[
+            (0, &[Btn::E], NONE),
+            (10, &[Btn::E, Btn::A], NONE),
+            (20, &[Btn::E, Btn::A, Btn::F], NONE),
+            (30, &[Btn::E, Btn::F], NONE),
+            (40, &[Btn::F], NONE),
+            (50, &[Btn::F, Btn::E], NONE),
+        ];
+        assert_eq!(run(&gestures, frames), [(20, 0), (50, 0)]);
+    }
+
+    #[test]
+    fn combo() {
+        // Quarter circle forward, punch.
+        let gestures = [Gesture::Combo(
+            vec![
+                vec![Btn::Down],
+                vec![Btn::Down, Btn::Right],
+                vec![Btn::Right],
+                vec![Btn::X],
+            ],
+            Duration::from_millis(100),
+        )];
+        let frames: &[(u64, &[Btn], _)] = &[
+            (0, &[], (0.0, 1.0)),
+            (30, &[], (0.7, 0.7)),
+            (60, &[], (1.0, 0.0)),
+            (100, &[Btn::X], (1.0, 0.0)),
+            // Pressing again doesn't reuse the same motion.
+            (120, &[], (1.0, 0.0)),
+            (140, &[Btn::X], (1.0, 0.0)),
+            (300, &[], NONE),
+            // Too slow.
+            (400, &[Btn::Down], NONE),
+            (450, &[Btn::Down, Btn::Right], NONE),
+            (600, &[Btn::Right], NONE),
+            (650, &[Btn::Right, Btn::X], NONE),
+            (700, &[], NONE),
+            // With the D-pad.
+            (800, &[Btn::Down], NONE),
+            (850, &[Btn::Down, Btn::Right], NONE),
+            (900, &[Btn::Right], NONE),
+            (950, &[Btn::Right, Btn::X], NONE),
+        ];
+        assert_eq!(run(&gestures, frames), [(100, 0), (950, 0)]);
+    }
+
+    #[test]
+    fn port_events() {
+        let (mock, mut port) = mock_port();
+        let quit = port.gesture(Gesture::Chord(vec![Btn::F, Btn::E]));
+        let hold = port.gesture(Gesture::Hold(Btn::A, Duration::from_secs(0)));
+
+        let pad = mock.connect(DeviceInfo::default());
+        mock.key(pad, 0x13A, true); // Select
+        mock.key(pad, 0x13B, true); // Start
+        let events = drain(&mut port);
+        assert_eq!(
+            events,
+            [
+                (0, Event::Connect),
+                (0, Event::Back(true)),
+                (0, Event::Forward(true)),
+                (0, Event::Gesture(quit)),
+            ]
+        );
+
+        mock.key(pad, 0x131, true);
+        assert_eq!(port.try_poll(), Some((0, Event::Accept(true))));
+        assert_eq!(port.try_poll(), Some((0, Event::Gesture(hold))));
+    }
+}
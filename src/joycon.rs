@@ -0,0 +1,225 @@
+// Nintendo Switch controllers, as `hid-nintendo` reports them.  The face
+// buttons are by position, so the default mapping puts A on the east and B
+// on the south.
+
+use crate::devices::{DeviceInfo, EvdevEvent};
+
+pub(crate) const JOYCON_L: u32 = 0x_057E_2006;
+pub(crate) const JOYCON_R: u32 = 0x_057E_2007;
+// Not a real device: a left and right Joy-Con paired into one.
+pub(crate) const JOYCON_PAIR: u32 = 0x_057E_2008;
+pub(crate) const PRO: u32 = 0x_057E_2009;
+
+const BTN_SOUTH: u16 = 0x130;
+const BTN_EAST: u16 = 0x131;
+const BTN_NORTH: u16 = 0x133;
+const BTN_WEST: u16 = 0x134;
+const BTN_Z: u16 = 0x135;
+const BTN_TL: u16 = 0x136;
+const BTN_TR: u16 = 0x137;
+const BTN_TL2: u16 = 0x138;
+const BTN_TR2: u16 = 0x139;
+const BTN_SELECT: u16 = 0x13A;
+const BTN_START: u16 = 0x13B;
+const BTN_THUMBL: u16 = 0x13D;
+const BTN_THUMBR: u16 = 0x13E;
+const BTN_DPAD_UP: u16 = 0x220;
+const BTN_DPAD_DOWN: u16 = 0x221;
+const BTN_DPAD_LEFT: u16 = 0x222;
+const BTN_DPAD_RIGHT: u16 = 0x223;
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+
+// Check if a device is a Nintendo controller with digital ZL and ZR.
+pub(crate) fn nintendo(hardware_id: u32) -> bool {
+    matches!(hardware_id, JOYCON_L | JOYCON_R | JOYCON_PAIR | PRO)
+}
+
+// Get the SL and SR buttons on a Joy-Con's rail, which `hid-nintendo`
+// reports as the other side's shoulder buttons.
+pub(crate) fn rail(hardware_id: u32) -> Option<(u16, u16)> {
+    match hardware_id {
+        JOYCON_L => Some((BTN_TR, BTN_TR2)),
+        JOYCON_R => Some((BTN_TL, BTN_TL2)),
+        _ => None,
+    }
+}
+
+// Get a Joy-Con's shoulder button (L or R).
+pub(crate) fn shoulder(hardware_id: u32) -> Option<u16> {
+    match hardware_id {
+        JOYCON_L => Some(BTN_TL),
+        JOYCON_R => Some(BTN_TR),
+        _ => None,
+    }
+}
+
+// Get the info of a left and right Joy-Con paired into one device.
+pub(crate) fn pair(left: &DeviceInfo, right: &DeviceInfo) -> DeviceInfo {
+    let mut keys = Vec::new();
+    for info in [left, right].iter() {
+        let (sl, sr) = rail(info.hardware_id).unwrap_or_default();
+        keys.extend(info.keys.iter().filter(|k| **k != sl && **k != sr));
+    }
+    keys.sort_unstable();
+    keys.dedup();
+
+    let uniq = if left.uniq.is_empty() && right.uniq.is_empty() {
+        String::new()
+    } else {
+        format!("{}+{}", left.uniq, right.uniq)
+    };
+
+    DeviceInfo {
+        hardware_id: JOYCON_PAIR,
+        name: "Nintendo Switch Joy-Con (L/R)".to_string(),
+        uniq,
+        abs: left.abs.iter().chain(right.abs.iter()).cloned().collect(),
+        keys,
+        path: left.path.clone(),
+    }
+}
+
+// Turn an event from a Joy-Con held sideways into the gamepad event for
+// where that input is when it's held sideways.  `None` for the buttons that
+// are out of reach, under the player's hands.
+pub(crate) fn sideways(
+    info: &DeviceInfo,
+    ev: &EvdevEvent,
+) -> Option<EvdevEvent> {
+    let (code, value) = (ev.ev_code as u16, ev.ev_value);
+    // Mirror an axis value across the middle of its range.
+    let flip = |code: u16| {
+        info.abs(code as u8)
+            .map(|abs| abs.min + abs.max - value)
+            .unwrap_or(-value)
+    };
+
+    let (code, value) = match (info.hardware_id, ev.ev_type) {
+        // Turned a quarter counterclockwise, with the stick on the left.
+        (JOYCON_L, 0x01) => match code {
+            BTN_DPAD_UP => (BTN_WEST, value),
+            BTN_DPAD_RIGHT => (BTN_NORTH, value),
+            BTN_DPAD_DOWN => (BTN_EAST, value),
+            BTN_DPAD_LEFT => (BTN_SOUTH, value),
+            BTN_TR => (BTN_TL, value),
+            BTN_TR2 => (BTN_TR, value),
+            BTN_SELECT => (BTN_START, value),
+            BTN_Z => (BTN_SELECT, value),
+            BTN_TL | BTN_TL2 => return None,
+            code => (code, value),
+        },
+        (JOYCON_L, 0x03) => match code {
+            ABS_X => (ABS_Y, flip(code)),
+            ABS_Y => (ABS_X, value),
+            code => (code, value),
+        },
+        // Turned a quarter clockwise, with the stick on the left.
+        (JOYCON_R, 0x01) => match code {
+            BTN_NORTH => (BTN_EAST, value),
+            BTN_EAST => (BTN_SOUTH, value),
+            BTN_SOUTH => (BTN_WEST, value),
+            BTN_WEST => (BTN_NORTH, value),
+            BTN_TL => (BTN_TL, value),
+            BTN_TL2 => (BTN_TR, value),
+            BTN_THUMBR => (BTN_THUMBL, value),
+            BTN_TR | BTN_TR2 => return None,
+            code => (code, value),
+        },
+        (JOYCON_R, 0x03) => match code {
+            ABS_RX => (ABS_Y, value),
+            ABS_RY => (ABS_X, flip(code)),
+            code => (code, value),
+        },
+        _ => (code, value),
+    };
+
+    Some(EvdevEvent {
+        ev_type: ev.ev_type,
+        ev_code: code as i16,
+        ev_value: value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{drain, mock_port};
+    use crate::{AbsInfo, Btn, Event};
+    use std::time::Duration;
+
+    #[test]
+    fn joycons() {
+        let (mock, mut port) = mock_port();
+        let range = AbsInfo {
+            min: -32767,
+            max: 32767,
+            ..Default::default()
+        };
+        let stick = |x, y| vec![(x, range), (y, range)];
+        let left = mock.connect(DeviceInfo {
+            hardware_id: JOYCON_L,
+            abs: stick(0, 1),
+            keys: vec![0x136, 0x137, 0x138, 0x139, 0x220],
+            ..Default::default()
+        });
+        let right = mock.connect(DeviceInfo {
+            hardware_id: JOYCON_R,
+            abs: stick(3, 4),
+            keys: vec![0x130, 0x136, 0x137, 0x138, 0x139],
+            ..Default::default()
+        });
+        drain(&mut port);
+
+        // ZL is digital.
+        mock.key(left, 0x138, true);
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().lrt(), Some((1.0, 0.0)));
+        mock.key(left, 0x138, false);
+
+        // Sideways, the arrow buttons are the face buttons.
+        port.set_sideways(0, true);
+        mock.key(left, 0x220, true);
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().btn(Btn::Y), Some(true));
+        assert_eq!(port.get(0).unwrap().btn(Btn::Up), Some(false));
+        mock.key(left, 0x220, false);
+        port.set_sideways(0, false);
+        drain(&mut port);
+
+        // Holding L and R pairs them.
+        port.joycon_pairing(true);
+        mock.key(left, 0x136, true);
+        mock.key(right, 0x137, true);
+        let events = drain(&mut port);
+        assert!(events.contains(&(0, Event::Disconnect)));
+        assert!(events.contains(&(1, Event::Disconnect)));
+        assert_eq!(events.last(), Some(&(0, Event::Connect)));
+        assert_eq!(port.count(), 1);
+        assert_eq!(port.get(0).unwrap().info().hardware_id, JOYCON_PAIR);
+        mock.key(left, 0x136, false);
+        mock.key(right, 0x137, false);
+
+        // Both halves feed the one device, without SL and SR.
+        mock.key(right, 0x130, true);
+        mock.key(left, 0x137, true);
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().btn(Btn::B), Some(true));
+        assert_eq!(port.get(0).unwrap().btn(Btn::Z), Some(false));
+
+        // Both rumble.
+        let duration = Duration::from_millis(100);
+        port.rumble(0, 1.0, 0.5, duration).unwrap();
+        assert_eq!(mock.rumbling(left), Some((65535, 32767, duration)));
+        assert_eq!(mock.rumbling(right), Some((65535, 32767, duration)));
+
+        // Unplugging one leaves the other.
+        mock.disconnect(right);
+        drain(&mut port);
+        assert_eq!(port.count(), 1);
+        assert_eq!(port.get(0).unwrap().info().hardware_id, JOYCON_L);
+    }
+}
@@ -1,3 +1,9 @@
+//! Get input from joysticks, gamepads, and other controllers.
+//!
+//! A [`Port`](struct.Port.html) connects to every controller, and can be used
+//! as an async `Stream`.  [`DeviceHandle`](struct.DeviceHandle.html)s share
+//! controller state with other threads.
+
 #![warn(missing_docs)]
 #![doc(
     html_logo_url = "https://libcala.github.io/stick/res/controller.png",
@@ -8,10 +14,36 @@
 mod event;
 pub use event::Event;
 
-
+mod actions;
+mod backend;
+mod builder;
+mod calibrate;
 mod devices;
+mod direction;
+mod gesture;
+mod joycon;
+mod mapping;
+mod nav;
+mod playstation;
+mod record;
+mod timer;
+mod wheel;
+mod xbox;
 
-pub use devices::{Btn, Device, Port, CONTROLLER_MAX};
+pub use actions::{ActionMap, Actions, Axis, AxisBinding, Binding};
+pub use backend::{Backend, MockBackend, RawInput};
+pub use builder::PortBuilder;
+pub use calibrate::{AxisRange, Calibration, Calibrator};
+pub use devices::{
+    AbsInfo, AxisKind, Btn, Device, DeviceClass, DeviceHandle, DeviceInfo,
+    DeviceState, Port, RawState, CONTROLLER_MAX,
+};
+pub use direction::{Direction, Dpad, Stick, StickShape};
+pub use gesture::Gesture;
+pub use mapping::{Control, Mapping, MappingWizard, Target};
+pub use nav::{Nav, NavRepeater};
+pub use record::Replay;
+pub use wheel::{Pedal, Pedals, Wheel};
 
 #[cfg(target_os = "android")]
 mod ffi {
@@ -35,3 +67,17 @@ mod ffi {
 }
 
 pub(crate) use self::ffi::NativeManager;
+
+use std::path::PathBuf;
+
+/// Get the directory stick keeps its files in: `$XDG_CONFIG_HOME/stick`, or
+/// `$HOME/.config/stick`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            let home = PathBuf::from(std::env::var_os("HOME")?);
+            Some(home.join(".config"))
+        })?;
+    Some(config.join("stick"))
+}
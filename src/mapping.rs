@@ -0,0 +1,835 @@
+use crate::{Axis, Btn, Device, DeviceInfo, StickShape};
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Names in stick's mapping format (like the files in `src/controllers/`).
+const BTNS: [(Btn, &str); 16] = [
+    (Btn::A, "Accept"),
+    (Btn::B, "Cancel"),
+    (Btn::X, "Action"),
+    (Btn::Y, "Upward"),
+    (Btn::W, "Throws"),
+    (Btn::Z, "AltAct"),
+    (Btn::L, "Crouch"),
+    (Btn::R, "Aiming"),
+    (Btn::F, "Escape"),
+    (Btn::E, "Pocket"),
+    (Btn::D, "Toggle"),
+    (Btn::C, "Camera"),
+    (Btn::Up, "Dpadup"),
+    (Btn::Down, "Dpaddn"),
+    (Btn::Left, "Dpadlt"),
+    (Btn::Right, "Dpadrt"),
+];
+const AXES: [(Axis, &str); 6] = [
+    (Axis::JoyX, "Joy-X"),
+    (Axis::JoyY, "Joy-Y"),
+    (Axis::CamX, "Pan-X"),
+    (Axis::CamY, "Pan-Y"),
+    (Axis::TrgL, "L THROTTLE"),
+    (Axis::TrgR, "R THROTTLE"),
+];
+const SHAPES: [(StickShape, &str); 4] = [
+    (StickShape::Raw, "Raw"),
+    (StickShape::Circle, "Circle"),
+    (StickShape::SquareToDisc, "Square"),
+    (StickShape::Octagon, "Octagon"),
+];
+
+// The built-in mappings of known controllers, by hardware ID.
+const BUILTIN: [(u32, &str); 17] = [
+    (0x_0079_1844, include_str!("controllers/s00791844.txt")),
+    (0x_045E_028E, include_str!("controllers/s045E028E.txt")),
+    (0x_045E_02D1, include_str!("controllers/s045E02D1.txt")),
+    (0x_045E_02E3, include_str!("controllers/s045E02E3.txt")),
+    (0x_045E_02EA, include_str!("controllers/s045E02EA.txt")),
+    (0x_045E_0719, include_str!("controllers/s045E0719.txt")),
+    (0x_045E_0B00, include_str!("controllers/s045E0B00.txt")),
+    (0x_045E_0B12, include_str!("controllers/s045E0B12.txt")),
+    (0x_054C_0268, include_str!("controllers/s054C0268.txt")),
+    (0x_054C_05C4, include_str!("controllers/s054C05C4.txt")),
+    (0x_054C_09CC, include_str!("controllers/s054C09CC.txt")),
+    (0x_054C_0CE6, include_str!("controllers/s054C0CE6.txt")),
+    (0x_057E_2006, include_str!("controllers/s057E2006.txt")),
+    (0x_057E_2007, include_str!("controllers/s057E2007.txt")),
+    (0x_057E_2009, include_str!("controllers/s057E2009.txt")),
+    (0x_07B5_0316, include_str!("controllers/s07B50316.txt")),
+    (0x_0E6F_0501, include_str!("controllers/s0E6F0501.txt")),
+];
+
+// Names in SDL's game controller format.
+const SDL_BTNS: [(Btn, &str); 16] = [
+    (Btn::B, "a"),
+    (Btn::A, "b"),
+    (Btn::Y, "x"),
+    (Btn::X, "y"),
+    (Btn::W, "leftshoulder"),
+    (Btn::Z, "rightshoulder"),
+    (Btn::L, "lefttrigger"),
+    (Btn::R, "righttrigger"),
+    (Btn::F, "back"),
+    (Btn::E, "start"),
+    (Btn::D, "leftstick"),
+    (Btn::C, "rightstick"),
+    (Btn::Up, "dpup"),
+    (Btn::Down, "dpdown"),
+    (Btn::Left, "dpleft"),
+    (Btn::Right, "dpright"),
+];
+const SDL_AXES: [(Axis, &str); 6] = [
+    (Axis::JoyX, "leftx"),
+    (Axis::JoyY, "lefty"),
+    (Axis::CamX, "rightx"),
+    (Axis::CamY, "righty"),
+    (Axis::TrgL, "lefttrigger"),
+    (Axis::TrgR, "righttrigger"),
+];
+
+// What the wizard asks for, in order: axes first, so that triggers that are
+// also buttons get their axis.
+const TARGETS: [Target; 22] = [
+    Target::Axis(Axis::JoyX),
+    Target::Axis(Axis::JoyY),
+    Target::Axis(Axis::CamX),
+    Target::Axis(Axis::CamY),
+    Target::Axis(Axis::TrgL),
+    Target::Axis(Axis::TrgR),
+    Target::Btn(Btn::B),
+    Target::Btn(Btn::A),
+    Target::Btn(Btn::Y),
+    Target::Btn(Btn::X),
+    Target::Btn(Btn::W),
+    Target::Btn(Btn::Z),
+    Target::Btn(Btn::L),
+    Target::Btn(Btn::R),
+    Target::Btn(Btn::F),
+    Target::Btn(Btn::E),
+    Target::Btn(Btn::D),
+    Target::Btn(Btn::C),
+    Target::Btn(Btn::Up),
+    Target::Btn(Btn::Down),
+    Target::Btn(Btn::Left),
+    Target::Btn(Btn::Right),
+];
+
+// Hat axes, an X then a Y for each of 4 hats.
+const ABS_HAT0X: u8 = 0x10;
+const ABS_HAT3Y: u8 = 0x17;
+// The last axis SDL counts (`ABS_MISC`).
+const ABS_MISC: u8 = 0x28;
+// First numbered joystick button, where mapping files count buttons from.
+const BTN_JOYSTICK: i32 = 0x120;
+
+/// A `Btn` or `Axis` that a control of a device is mapped to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A button.
+    Btn(Btn),
+    /// An axis, which the wizard asks to be moved right, down or pulled.
+    Axis(Axis),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match *self {
+            Target::Axis(Axis::JoyX) => "main stick right",
+            Target::Axis(Axis::JoyY) => "main stick down",
+            Target::Axis(Axis::CamX) => "camera stick right",
+            Target::Axis(Axis::CamY) => "camera stick down",
+            Target::Axis(Axis::TrgL) => "left trigger",
+            Target::Axis(Axis::TrgR) => "right trigger",
+            Target::Btn(Btn::B) => "bottom face button",
+            Target::Btn(Btn::A) => "right face button",
+            Target::Btn(Btn::Y) => "left face button",
+            Target::Btn(Btn::X) => "top face button",
+            Target::Btn(Btn::W) => "left shoulder button",
+            Target::Btn(Btn::Z) => "right shoulder button",
+            Target::Btn(Btn::L) => "left trigger button",
+            Target::Btn(Btn::R) => "right trigger button",
+            Target::Btn(Btn::F) => "select / back button",
+            Target::Btn(Btn::E) => "start button",
+            Target::Btn(Btn::D) => "main stick click",
+            Target::Btn(Btn::C) => "camera stick click",
+            Target::Btn(Btn::Up) => "D-pad up",
+            Target::Btn(Btn::Down) => "D-pad down",
+            Target::Btn(Btn::Left) => "D-pad left",
+            Target::Btn(Btn::Right) => "D-pad right",
+        };
+        f.write_str(text)
+    }
+}
+
+/// A raw control of a device, found by the `MappingWizard`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Control {
+    /// A button (`EV_KEY` code).
+    Button(u16),
+    /// A whole axis (`ABS_*` code), and whether it's inverted.
+    Axis(u8, bool),
+    /// Half of an axis (`ABS_*` code), such as one direction of a hat: the
+    /// half above the middle if `true`.
+    HalfAxis(u8, bool),
+}
+
+impl Control {
+    // Check if two controls use the same input.
+    fn overlaps(self, other: Control) -> bool {
+        match (self, other) {
+            (Control::Button(a), Control::Button(b)) => a == b,
+            (Control::HalfAxis(a, x), Control::HalfAxis(b, y)) => {
+                a == b && x == y
+            }
+            (
+                Control::Axis(a, _),
+                Control::Axis(b, _) | Control::HalfAxis(b, _),
+            )
+            | (Control::HalfAxis(a, _), Control::Axis(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// A mapping from the raw controls of a device to `Btn`s and `Axis`es.
+///
+/// It's saved in stick's mapping format (like the files in
+/// `src/controllers/`), with buttons counted from `BTN_JOYSTICK` (0x120),
+/// `//` comments, axes with a `+` or `-` for half of an axis (or `-` for
+/// an inverted one), and the shape of the sticks' gates (`Raw`, `Circle`,
+/// `Square` or `Octagon`, see `StickShape`):
+///
+/// ```text
+/// 16 => Cancel
+/// 17 => Accept
+/// // Axis
+/// 0 => Joy-X
+/// 17- => Dpadup
+/// 16 => Dpad-X
+/// Sticks => Octagon
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mapping {
+    controls: Vec<(Target, Control)>,
+    shape: Option<StickShape>,
+}
+
+impl Mapping {
+    /// Create an empty mapping.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a control to a target, replacing what it was mapped to.
+    pub fn set(&mut self, target: Target, control: Control) {
+        self.controls.retain(|(t, _)| *t != target);
+        self.controls.push((target, control));
+        // In the order the wizard asks for them.
+        self.controls
+            .sort_by_key(|(t, _)| TARGETS.iter().position(|a| a == t));
+    }
+
+    /// Get the control mapped to a target.
+    pub fn get(&self, target: Target) -> Option<Control> {
+        self.controls
+            .iter()
+            .find(|(t, _)| *t == target)
+            .map(|(_, control)| *control)
+    }
+
+    // Get every target and the control mapped to it.
+    pub(crate) fn controls(&self) -> &[(Target, Control)] {
+        &self.controls
+    }
+
+    /// Set how the device's sticks are mapped to a circle, or `None` for the
+    /// `StickShape` of its built-in mapping.
+    pub fn set_stick_shape(&mut self, shape: Option<StickShape>) {
+        self.shape = shape;
+    }
+
+    /// Get how the device's sticks are mapped to a circle, if the mapping
+    /// says.
+    pub fn stick_shape(&self) -> Option<StickShape> {
+        self.shape
+    }
+
+    // Get the built-in mapping of a known controller (`src/controllers/`).
+    pub(crate) fn builtin(hardware_id: u32) -> Option<Self> {
+        let (_, text) = BUILTIN.iter().find(|(id, _)| *id == hardware_id)?;
+        text.parse().ok()
+    }
+
+    /// Load a mapping in stick's mapping format.
+    pub fn load<R: Read>(mut input: R) -> io::Result<Self> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        text.parse()
+    }
+
+    /// Save a mapping in stick's mapping format.
+    pub fn save<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+
+    /// Get the default directory for mappings: `$XDG_CONFIG_HOME/stick/
+    /// mappings`, or `$HOME/.config/stick/mappings`.
+    pub fn dir() -> Option<PathBuf> {
+        Some(crate::config_dir()?.join("mappings"))
+    }
+
+    /// Get the file name of a device's mapping, from its vendor and product
+    /// ID, like `s054C0268.txt`.
+    pub fn file_name(info: &DeviceInfo) -> String {
+        format!("s{:08X}.txt", info.hardware_id)
+    }
+
+    /// Load a device's mapping from a directory.  Returns `Ok(None)` if it
+    /// doesn't have one.
+    pub fn load_profile(
+        dir: &Path,
+        info: &DeviceInfo,
+    ) -> io::Result<Option<Self>> {
+        match fs::File::open(dir.join(Self::file_name(info))) {
+            Ok(file) => Self::load(file).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save a device's mapping to a directory, creating it if needed, with
+    /// the device's name at the top.
+    pub fn save_profile(
+        &self,
+        dir: &Path,
+        info: &DeviceInfo,
+    ) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let mut file = fs::File::create(dir.join(Self::file_name(info)))?;
+        writeln!(file, "// {}\n", info.name.to_lowercase())?;
+        self.save(file)
+    }
+
+    /// Get the mapping as a line of SDL's game controller database
+    /// (`gamecontrollerdb.txt`).  SDL counts a device's buttons and axes in
+    /// its own order, which comes from the buttons and axes in `info`.  The
+    /// GUID is for a USB device.
+    pub fn sdl(&self, info: &DeviceInfo) -> String {
+        let swap16 = |v: u32| (v as u16).swap_bytes();
+        let mut line = format!(
+            "03000000{:04x}0000{:04x}0000{:04x}0000,{},",
+            swap16(info.hardware_id >> 16),
+            swap16(info.hardware_id),
+            0,
+            info.name.replace(',', " "),
+        );
+
+        let mut done = Vec::new();
+        // Analog triggers win over their digital buttons.
+        let axes = SDL_AXES.iter().map(|(a, n)| (Target::Axis(*a), *n));
+        let btns = SDL_BTNS.iter().map(|(b, n)| (Target::Btn(*b), *n));
+        for (target, name) in axes.chain(btns) {
+            let control = match self.get(target) {
+                Some(control) if !done.contains(&name) => control,
+                _ => continue,
+            };
+            if let Some(control) = sdl_control(info, control) {
+                line.push_str(&format!("{}:{},", name, control));
+                done.push(name);
+            }
+        }
+        line.push_str("platform:Linux,");
+        line
+    }
+}
+
+// Get SDL's name for a control of a device.
+fn sdl_control(info: &DeviceInfo, control: Control) -> Option<String> {
+    let has = |code: u8| info.abs(code).is_some();
+    let axis_index = |code: u8| {
+        (0..code)
+            .filter(|c| has(*c) && !(ABS_HAT0X..=ABS_HAT3Y).contains(c))
+            .count()
+    };
+    Some(match control {
+        Control::Button(code) => {
+            // Joystick buttons first, then the lower codes.
+            let mut keys = info.keys.clone();
+            keys.sort_by_key(|k| (*k < BTN_JOYSTICK as u16, *k));
+            format!("b{}", keys.iter().position(|k| *k == code)?)
+        }
+        Control::HalfAxis(code, positive)
+            if (ABS_HAT0X..=ABS_HAT3Y).contains(&code) =>
+        {
+            let hat = (ABS_HAT0X..code & !1)
+                .step_by(2)
+                .filter(|c| has(*c) || has(c + 1))
+                .count();
+            // Up, right, down and left.
+            let mask = match (code & 1 == 1, positive) {
+                (true, false) => 1,
+                (false, true) => 2,
+                (true, true) => 4,
+                (false, false) => 8,
+            };
+            format!("h{}.{}", hat, mask)
+        }
+        Control::HalfAxis(code, positive) if code < ABS_MISC => {
+            let sign = if positive { '+' } else { '-' };
+            format!("{}a{}", sign, axis_index(code))
+        }
+        Control::Axis(code, inverted) if code < ABS_MISC => {
+            let tilde = if inverted { "~" } else { "" };
+            format!("a{}{}", axis_index(code), tilde)
+        }
+        _ => return None,
+    })
+}
+
+impl FromStr for Mapping {
+    type Err = io::Error;
+
+    fn from_str(text: &str) -> io::Result<Self> {
+        let mut mapping = Mapping::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad mapping on line {}: {}", number + 1, line),
+                )
+            };
+
+            let mut parts = line.splitn(2, "=>");
+            let code = parts.next().unwrap().trim();
+            // Notes in brackets, like "(unipolar)", aren't part of the name.
+            let name = parts.next().ok_or_else(error)?;
+            let name = name.split('(').next().unwrap().trim();
+            // Controls that aren't there or aren't used.
+            if code == "_" || code == "?" || name.starts_with('[') {
+                continue;
+            }
+            if code == "Sticks" {
+                let shape = SHAPES.iter().find(|(_, n)| *n == name);
+                mapping.shape = Some(shape.ok_or_else(error)?.0);
+                continue;
+            }
+            let (code, sign) = match code.strip_suffix(['+', '-']) {
+                Some(number) => (number, code.chars().last()),
+                None => (code, None),
+            };
+            let code: i32 = code.trim().parse().map_err(|_| error())?;
+            let abs = || u8::try_from(code).map_err(|_| error());
+
+            if let Some((btn, _)) = BTNS.iter().find(|(_, n)| *n == name) {
+                let control = match sign {
+                    Some(sign) => Control::HalfAxis(abs()?, sign == '+'),
+                    None => Control::Button(
+                        u16::try_from(code + BTN_JOYSTICK)
+                            .map_err(|_| error())?,
+                    ),
+                };
+                mapping.set(Target::Btn(*btn), control);
+            } else if let Some((axis, _)) = AXES
+                .iter()
+                .find(|(_, n)| *n == name)
+                .or_else(|| alias(name))
+            {
+                let control = Control::Axis(abs()?, sign == Some('-'));
+                mapping.set(Target::Axis(*axis), control);
+            } else if let Some((neg, pos)) = dpad(name) {
+                let code = abs()?;
+                mapping.set(Target::Btn(neg), Control::HalfAxis(code, false));
+                mapping.set(Target::Btn(pos), Control::HalfAxis(code, true));
+            } else {
+                return Err(error());
+            }
+        }
+
+        Ok(mapping)
+    }
+}
+
+// Other names for axes.
+fn alias(name: &str) -> Option<&'static (Axis, &'static str)> {
+    match name {
+        "Stationary Throttle" => Some(&AXES[4]),
+        _ => None,
+    }
+}
+
+// A D-pad axis, as both its halves.
+fn dpad(name: &str) -> Option<(Btn, Btn)> {
+    match name {
+        "Dpad-X" => Some((Btn::Left, Btn::Right)),
+        "Dpad-Y" => Some((Btn::Up, Btn::Down)),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (target, control) in self.controls.iter() {
+            if let (Target::Btn(btn), Control::Button(code)) = (target, control)
+            {
+                let name = BTNS.iter().find(|(b, _)| b == btn).unwrap().1;
+                writeln!(f, "{} => {}", *code as i32 - BTN_JOYSTICK, name)?;
+            }
+        }
+        writeln!(f, "// Axis")?;
+        for (target, control) in self.controls.iter() {
+            let (code, sign) = match *control {
+                Control::Button(_) => continue,
+                Control::Axis(code, inverted) => {
+                    (code, if inverted { "-" } else { "" })
+                }
+                Control::HalfAxis(code, true) => (code, "+"),
+                Control::HalfAxis(code, false) => (code, "-"),
+            };
+            let name = match target {
+                Target::Btn(btn) => {
+                    BTNS.iter().find(|(b, _)| b == btn).unwrap().1
+                }
+                Target::Axis(axis) => {
+                    AXES.iter().find(|(a, _)| a == axis).unwrap().1
+                }
+            };
+            writeln!(f, "{}{} => {}", code, sign, name)?;
+        }
+        if let Some(shape) = self.shape {
+            let name = SHAPES.iter().find(|(s, _)| *s == shape).unwrap().1;
+            writeln!(f, "Sticks => {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks a player through mapping an unknown controller: it asks for each
+/// `Target` in turn, and maps the first control that's pressed or moved
+/// most of the way, once everything is back at rest.  Axes that drift or
+/// rest away from the middle are measured from where they were when the
+/// wizard started.
+///
+/// ```norun
+/// // Let go of everything...
+/// let mut wizard = MappingWizard::new(port.get(id).unwrap());
+/// while let Some(target) = wizard.target() {
+///     println!("Press the {}", target);
+///     port.poll_blocking(None);
+///     wizard.update(port.get(id).unwrap());
+/// }
+/// let info = port.get(id).unwrap().info();
+/// wizard.mapping().save_profile(&Mapping::dir().unwrap(), info)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct MappingWizard {
+    // Where each axis rests, and its range.
+    rest: Vec<(u8, i32, i32)>,
+    // Buttons held when the wizard started (stuck or toggled).
+    held: Vec<u16>,
+    // Index of the target being asked for.
+    next: usize,
+    // Whether a control was just mapped, and isn't back at rest yet.
+    waiting: bool,
+    mapping: Mapping,
+}
+
+impl MappingWizard {
+    /// Start mapping a device, which should be at rest.
+    pub fn new(device: &Device) -> Self {
+        let raw = device.raw();
+        let rest = device
+            .info()
+            .abs
+            .iter()
+            .filter(|(_, abs)| abs.max > abs.min)
+            .map(|(code, abs)| (*code, raw.abs(*code), abs.max - abs.min))
+            .collect();
+
+        MappingWizard {
+            rest,
+            held: raw.keys().collect(),
+            next: 0,
+            waiting: false,
+            mapping: Mapping::new(),
+        }
+    }
+
+    /// Get the target to ask the player for, or `None` when it's done.
+    pub fn target(&self) -> Option<Target> {
+        TARGETS.get(self.next).cloned()
+    }
+
+    /// Skip the target being asked for, because the device doesn't have it.
+    pub fn skip(&mut self) {
+        self.next += 1;
+    }
+
+    /// Look for the control being pressed or moved.  Call this every frame.
+    /// Returns the target and control when one is mapped.
+    pub fn update(&mut self, device: &Device) -> Option<(Target, Control)> {
+        let raw = device.raw();
+        let held = &self.held;
+        let pressed: Vec<u16> =
+            raw.keys().filter(|k| !held.contains(k)).collect();
+        let moved: Vec<(u8, i32, i32)> = self
+            .rest
+            .iter()
+            .map(|(code, rest, range)| (*code, raw.abs(*code) - rest, *range))
+            .collect();
+
+        // Wait for the last control to be let go first.
+        if self.waiting {
+            self.waiting = !pressed.is_empty()
+                || moved
+                    .iter()
+                    .any(|(_, delta, range)| delta.abs() * 8 > *range);
+            return None;
+        }
+        let target = self.target()?;
+
+        // Pressed a quarter of the range, past any drift and noise.
+        let axis = moved
+            .iter()
+            .filter(|(_, delta, range)| delta.abs() * 4 >= *range)
+            .max_by_key(|(_, delta, range)| {
+                (i64::from(delta.abs()) << 32) / i64::from(*range)
+            })
+            .map(|(code, delta, _)| (*code, *delta > 0));
+        let control = match target {
+            Target::Axis(_) => {
+                axis.map(|(code, positive)| Control::Axis(code, !positive))
+            }
+            Target::Btn(_) => pressed
+                .into_iter()
+                .map(Control::Button)
+                .chain(axis.map(|(code, pos)| Control::HalfAxis(code, pos)))
+                .find(|control| !self.mapped(*control)),
+        };
+        let control = control.filter(|control| !self.mapped(*control))?;
+
+        self.mapping.set(target, control);
+        self.next += 1;
+        self.waiting = true;
+        Some((target, control))
+    }
+
+    /// Get the mapping made so far.
+    pub fn mapping(&self) -> &Mapping {
+        &self.mapping
+    }
+
+    // Check if a control is already mapped.
+    fn mapped(&self, control: Control) -> bool {
+        self.mapping
+            .controls
+            .iter()
+            .any(|(_, c)| c.overlaps(control))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{drain, mock_port};
+    use crate::{AbsInfo, MockBackend, Port};
+
+    fn pad() -> DeviceInfo {
+        let range = |min, max| AbsInfo {
+            min,
+            max,
+            ..Default::default()
+        };
+        DeviceInfo {
+            hardware_id: 0x_1234_5678,
+            name: "Pad".to_string(),
+            abs: vec![
+                (0, range(-128, 127)),
+                (1, range(-128, 127)),
+                (2, range(0, 255)),
+                (0x10, range(-1, 1)),
+                (0x11, range(-1, 1)),
+            ],
+            keys: vec![0x130, 0x131, 0x133, 0x134],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn wizard() {
+        let (mock, mut port) = mock_port();
+        let id = mock.connect(pad());
+        // A trigger that rests pulled a little.
+        mock.abs(id, 2, 20);
+        drain(&mut port);
+
+        let mut wizard = MappingWizard::new(port.get(0).unwrap());
+        let mut act = |ev_type, code, value, wizard: &mut MappingWizard| {
+            mock.event(id, ev_type, code, value);
+            mock.sync(id);
+            drain(&mut port);
+            wizard.update(port.get(0).unwrap())
+        };
+
+        // Noise and drift don't count.
+        assert_eq!(act(3, 1, 10, &mut wizard), None);
+        assert_eq!(act(3, 2, 60, &mut wizard), None);
+        act(3, 2, 20, &mut wizard);
+        // Main stick right, upside down.
+        let found = act(3, 0, -128, &mut wizard);
+        assert_eq!(
+            found,
+            Some((Target::Axis(Axis::JoyX), Control::Axis(0, true)))
+        );
+        // Nothing until it's let go.
+        assert_eq!(act(3, 1, 127, &mut wizard), None);
+        act(3, 0, 0, &mut wizard);
+        act(3, 1, 0, &mut wizard);
+        let found = act(3, 1, 127, &mut wizard);
+        assert_eq!(found.unwrap().1, Control::Axis(1, false));
+        act(3, 1, 0, &mut wizard);
+        // No camera stick.
+        wizard.skip();
+        wizard.skip();
+        let found = act(3, 2, 255, &mut wizard);
+        assert_eq!(found.unwrap().1, Control::Axis(2, false));
+        act(3, 2, 20, &mut wizard);
+        wizard.skip();
+
+        // Buttons, and the D-pad on a hat.
+        let found = act(1, 0x130, 1, &mut wizard);
+        assert_eq!(found, Some((Target::Btn(Btn::B), Control::Button(0x130))));
+        act(1, 0x130, 0, &mut wizard);
+        // Already mapped.
+        assert_eq!(act(1, 0x130, 1, &mut wizard), None);
+        act(1, 0x130, 0, &mut wizard);
+        while wizard.target() != Some(Target::Btn(Btn::Up)) {
+            wizard.skip();
+        }
+        let found = act(3, 0x11, -1, &mut wizard);
+        assert_eq!(found.unwrap().1, Control::HalfAxis(0x11, false));
+        act(3, 0x11, 0, &mut wizard);
+
+        let mapping = wizard.mapping();
+        let text = mapping.to_string();
+        assert_eq!(
+            text,
+            "16 => Cancel\n// Axis\n0- => Joy-X\n1 => Joy-Y\n\
+             2 => L THROTTLE\n17- => Dpadup\n"
+        );
+        assert_eq!(&text.parse::<Mapping>().unwrap(), mapping);
+        assert_eq!(
+            mapping.sdl(&pad()),
+            "03000000341200007856000000000000,Pad,leftx:a0~,lefty:a1,\
+             lefttrigger:a2,a:b0,dpup:h0.1,platform:Linux,"
+        );
+    }
+
+    #[test]
+    fn mapping_files() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src/controllers");
+        for file in fs::read_dir(dir).unwrap() {
+            let path = file.unwrap().path();
+            let mapping = Mapping::load(fs::File::open(&path).unwrap());
+            assert!(mapping.is_ok(), "{:?}", path);
+            // And it's built in.
+            let name = path.file_name().unwrap();
+            let builtin = BUILTIN.iter().find(|(id, _)| {
+                let info = DeviceInfo {
+                    hardware_id: *id,
+                    ..Default::default()
+                };
+                *name == *Mapping::file_name(&info)
+            });
+            assert!(builtin.is_some(), "{:?}", path);
+        }
+
+        let ps3 = include_str!("controllers/s054C0268.txt");
+        let ps3: Mapping = ps3.parse().unwrap();
+        let accept = ps3.get(Target::Btn(Btn::A));
+        assert_eq!(accept, Some(Control::Button(0x131)));
+        let trigger = ps3.get(Target::Axis(Axis::TrgL));
+        assert_eq!(trigger, Some(Control::Axis(2, false)));
+        let flight = include_str!("controllers/s07B50316.txt");
+        let flight: Mapping = flight.parse().unwrap();
+        let left = flight.get(Target::Btn(Btn::Left));
+        assert_eq!(left, Some(Control::HalfAxis(0x10, false)));
+
+        // The stick shape.
+        assert_eq!(ps3.stick_shape(), None);
+        let gamecube = Mapping::builtin(0x_0079_1844).unwrap();
+        assert_eq!(gamecube.stick_shape(), Some(StickShape::Octagon));
+        let text = "0 => Joy-X\nSticks => Square\n";
+        let square: Mapping = text.parse().unwrap();
+        assert_eq!(square.stick_shape(), Some(StickShape::SquareToDisc));
+        assert_eq!(square.to_string(), format!("// Axis\n{}", text));
+        assert!("Sticks => Round".parse::<Mapping>().is_err());
+    }
+
+    #[test]
+    fn profile() {
+        let info = pad();
+        let mut mapping = Mapping::new();
+        mapping.set(Target::Axis(Axis::JoyX), Control::Axis(0, true));
+        mapping.set(Target::Axis(Axis::TrgL), Control::Axis(2, false));
+        mapping.set(Target::Btn(Btn::B), Control::Button(0x133));
+        mapping.set(Target::Btn(Btn::Up), Control::HalfAxis(0x11, false));
+        mapping.set_stick_shape(Some(StickShape::SquareToDisc));
+        let dir = std::env::temp_dir()
+            .join(format!("stick-mappings-{}", std::process::id()));
+        assert_eq!(Mapping::load_profile(&dir, &info).unwrap(), None);
+        mapping.save_profile(&dir, &info).unwrap();
+        let loaded = Mapping::load_profile(&dir, &info).unwrap();
+        assert_eq!(loaded.as_ref(), Some(&mapping));
+
+        // Used instead of the built-in mapping when it's plugged in.
+        let (mock, mut port) = mock_port();
+        port.mapping_dir(Some(dir.clone()));
+        let id = mock.connect(info);
+        for (ev_type, code, value) in
+            [(1, 0x133, 1), (3, 0, -128), (3, 2, 255), (3, 0x11, -1)]
+        {
+            mock.event(id, ev_type, code, value);
+        }
+        mock.sync(id);
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        assert_eq!(device.mapping().as_ref(), Some(&mapping));
+        assert_eq!(device.stick_shape(), StickShape::SquareToDisc);
+        let state = device.snapshot();
+        assert_eq!(state.btn(Btn::B), Some(true));
+        assert_eq!(state.btn(Btn::X), Some(false));
+        assert_eq!(state.btn(Btn::Up), Some(true));
+        assert_eq!(state.btn(Btn::L), Some(true));
+        assert_eq!(state.joy.0, 1.0);
+        assert_eq!(state.lrt.0, 1.0);
+
+        port.set_mapping(0, None);
+        mock.event(id, 1, 0x130, 1);
+        mock.sync(id);
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().btn(Btn::B), Some(true));
+        assert_eq!(port.get(0).unwrap().stick_shape(), StickShape::Circle);
+
+        // Also for devices already plugged in when the `Port` is built, but
+        // only from a directory that's set.
+        let mock = MockBackend::new();
+        mock.connect(pad());
+        let builder = Port::builder().backend(mock.clone());
+        let mut port = builder.mapping_dir(Some(dir.clone())).build();
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().mapping().as_ref(), Some(&mapping));
+        let mut port = Port::with_backend(mock.clone());
+        mock.connect(pad());
+        drain(&mut port);
+        assert_ne!(port.get(0).unwrap().mapping(), Some(mapping));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
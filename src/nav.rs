@@ -0,0 +1,256 @@
+use crate::{Btn, DeviceState, Dpad, Port, Stick, CONTROLLER_MAX};
+
+use std::time::{Duration, Instant};
+
+/// A menu navigation command, from `NavRepeater`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Nav {
+    /// Move up.
+    Up,
+    /// Move down.
+    Down,
+    /// Move left.
+    Left,
+    /// Move right.
+    Right,
+    /// Select the current item (`Btn::A`).
+    Accept,
+    /// Go back (`Btn::B`).
+    Back,
+}
+
+/// Turns the D-pad and main stick into menu navigation, repeating the
+/// direction while it's held like a keyboard does:
+///
+/// ```norun
+/// let mut nav = NavRepeater::new();
+/// loop {
+///     port.poll_blocking(nav.timeout());
+///     for (id, nav) in nav.update(&port) {
+///         // ...
+///     }
+/// }
+/// ```
+///
+/// Accept and Back come from `Btn::A` and `Btn::B`, so they follow each
+/// controller's layout (e.g. A and B are swapped on Xbox controllers).
+#[derive(Debug)]
+pub struct NavRepeater {
+    // How long a direction is held before it repeats.
+    delay: Duration,
+    // How long between repeats.
+    rate: Duration,
+    players: Vec<Player>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Player {
+    // The main stick as a D-pad.
+    stick: Dpad,
+    // The direction held, and when to repeat it next.
+    held: Option<(Nav, Instant)>,
+    // Buttons held last update.
+    btns: u32,
+}
+
+impl Default for NavRepeater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NavRepeater {
+    /// Create a repeater that waits 400ms before repeating, then repeats
+    /// every 100ms.
+    pub fn new() -> Self {
+        let player = Player {
+            stick: Dpad::four(),
+            held: None,
+            btns: 0,
+        };
+
+        NavRepeater {
+            delay: Duration::from_millis(400),
+            rate: Duration::from_millis(100),
+            players: vec![player; CONTROLLER_MAX],
+        }
+    }
+
+    /// Set how long a direction is held before it starts repeating.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set how long between repeats.
+    pub fn rate(mut self, rate: Duration) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Read every controller from `port`, returning navigation for what was
+    /// pressed, and for directions that are due to repeat.
+    pub fn update(&mut self, port: &Port) -> Vec<(u8, Nav)> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for player in 0..CONTROLLER_MAX as u8 {
+            let state = port
+                .get(player)
+                .map(|device| device.snapshot())
+                .unwrap_or_default();
+            self.step(player, &state, now, &mut out);
+        }
+        out
+    }
+
+    /// How long until a held direction repeats, for the timeout of
+    /// `Port::poll_blocking()`.  `None` if nothing is held.
+    pub fn timeout(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.players
+            .iter()
+            .filter_map(|player| player.held)
+            .map(|(_, next)| next.saturating_duration_since(now))
+            .min()
+    }
+
+    fn step(
+        &mut self,
+        id: u8,
+        state: &DeviceState,
+        now: Instant,
+        out: &mut Vec<(u8, Nav)>,
+    ) {
+        let player = &mut self.players[id as usize];
+
+        // Drive the virtual D-pad, ignoring its events.
+        let _ = player
+            .stick
+            .update(state.joy().map(Stick::from).unwrap_or_default());
+        let stick = player.stick.direction();
+        let dir = [
+            (Btn::Up, Nav::Up),
+            (Btn::Down, Nav::Down),
+            (Btn::Left, Nav::Left),
+            (Btn::Right, Nav::Right),
+        ]
+        .iter()
+        .find(|(btn, _)| {
+            state.btn(*btn) == Some(true) || stick.is_some_and(|d| d.has(*btn))
+        })
+        .map(|(_, nav)| *nav);
+
+        player.held = match (dir, player.held) {
+            (None, _) => None,
+            (Some(dir), Some((held, next))) if dir == held => {
+                if now >= next {
+                    out.push((id, dir));
+                    // Don't bunch up repeats after a long frame.
+                    let next = if next + self.rate < now {
+                        now + self.rate
+                    } else {
+                        next + self.rate
+                    };
+                    Some((dir, next))
+                } else {
+                    Some((held, next))
+                }
+            }
+            (Some(dir), _) => {
+                out.push((id, dir));
+                Some((dir, now + self.delay))
+            }
+        };
+
+        let btns = state.btns;
+        for &(btn, nav) in [(Btn::A, Nav::Accept), (Btn::B, Nav::Back)].iter() {
+            let bit = 1 << btn as u8;
+            if btns & bit != 0 && player.btns & bit == 0 {
+                out.push((id, nav));
+            }
+        }
+        player.btns = btns;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{drain, mock_port};
+
+    fn state(btns: &[Btn], joy: (f32, f32)) -> DeviceState {
+        DeviceState {
+            btns: btns.iter().fold(0, |m, b| m | 1 << *b as u8),
+            joy,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn repeat() {
+        let start = Instant::now();
+        let mut repeater = NavRepeater::new()
+            .delay(Duration::from_millis(300))
+            .rate(Duration::from_millis(50));
+        let mut run = |ms, btns: &[Btn], joy| {
+            let mut out = Vec::new();
+            let now = start + Duration::from_millis(ms);
+            repeater.step(1, &state(btns, joy), now, &mut out);
+            out.into_iter().map(|(_, nav)| nav).collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(0, &[Btn::Down], (0.0, 0.0)), [Nav::Down]);
+        assert_eq!(run(200, &[Btn::Down], (0.0, 0.0)), []);
+        assert_eq!(run(300, &[Btn::Down], (0.0, 0.0)), [Nav::Down]);
+        assert_eq!(run(320, &[Btn::Down], (0.0, 0.0)), []);
+        assert_eq!(run(350, &[Btn::Down], (0.0, 0.0)), [Nav::Down]);
+        // A long frame only repeats once.
+        assert_eq!(run(600, &[Btn::Down], (0.0, 0.0)), [Nav::Down]);
+        assert_eq!(run(640, &[Btn::Down], (0.0, 0.0)), []);
+        assert_eq!(run(650, &[Btn::Down], (0.0, 0.0)), [Nav::Down]);
+
+        // The stick works the same, and changing direction starts over.
+        assert_eq!(run(660, &[], (0.9, 0.0)), [Nav::Right]);
+        assert_eq!(run(900, &[], (0.9, 0.0)), []);
+        assert_eq!(run(960, &[], (0.9, 0.0)), [Nav::Right]);
+        assert_eq!(run(970, &[], (0.0, 0.0)), []);
+        assert_eq!(run(980, &[], (0.9, 0.0)), [Nav::Right]);
+    }
+
+    #[test]
+    fn accept_back() {
+        let start = Instant::now();
+        let mut repeater = NavRepeater::new();
+        let mut run = |btns: &[Btn]| {
+            let mut out = Vec::new();
+            repeater.step(0, &state(btns, (0.0, 0.0)), start, &mut out);
+            out
+        };
+
+        assert_eq!(run(&[Btn::A]), [(0, Nav::Accept)]);
+        // No repeat.
+        assert_eq!(run(&[Btn::A]), []);
+        assert_eq!(run(&[Btn::A, Btn::B]), [(0, Nav::Back)]);
+        assert_eq!(run(&[]), []);
+        assert_eq!(run(&[Btn::A]), [(0, Nav::Accept)]);
+    }
+
+    #[test]
+    fn swapped_layout() {
+        use crate::DeviceInfo;
+
+        let (mock, mut port) = mock_port();
+        let mut repeater = NavRepeater::new();
+        let xbox = mock.connect(DeviceInfo {
+            hardware_id: 0x_0E6F_0501,
+            ..Default::default()
+        });
+        let other = mock.connect(DeviceInfo::default());
+        // BTN_SOUTH on both.
+        mock.key(xbox, 0x130, true);
+        mock.key(other, 0x130, true);
+        drain(&mut port);
+
+        assert_eq!(repeater.update(&port), [(0, Nav::Accept), (1, Nav::Back)]);
+    }
+}
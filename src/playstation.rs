@@ -0,0 +1,53 @@
+// PlayStation controllers: the DualShock 4 and DualSense, as `hid-sony` and
+// `hid-playstation` report them, with a touchpad that clicks (its own evdev
+// device), a lightbar and unipolar triggers.
+
+// Check if a device is a DualShock 4 or DualSense.
+pub(crate) fn playstation(hardware_id: u32) -> bool {
+    matches!(
+        hardware_id,
+        // DualShock 4 (both revisions) and DualSense.
+        0x_054C_05C4 | 0x_054C_09CC | 0x_054C_0CE6
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{drain, mock_port};
+    use crate::{AbsInfo, AxisKind, Btn, DeviceInfo, Event};
+
+    #[test]
+    fn playstation() {
+        let (mock, mut port) = mock_port();
+        let trigger = AbsInfo {
+            min: 0,
+            max: 255,
+            ..Default::default()
+        };
+        let id = mock.connect(DeviceInfo {
+            hardware_id: 0x_054C_09CC,
+            abs: vec![(2, trigger), (5, trigger)],
+            keys: vec![0x110, 0x13A, 0x13B, 0x13C],
+            ..Default::default()
+        });
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        assert_eq!(device.axis_kind(2), AxisKind::Unipolar);
+        assert_eq!(device.axis_kind(5), AxisKind::Unipolar);
+
+        // Share, Options, PS and the touchpad's click.
+        for &code in [0x13A, 0x13B, 0x13C, 0x110].iter() {
+            mock.key(id, code, true);
+        }
+        let events = drain(&mut port);
+        assert!(events.contains(&(0, Event::Exit)));
+        let device = port.get(0).unwrap();
+        assert_eq!(device.btn(Btn::F), Some(true));
+        assert_eq!(device.btn(Btn::E), Some(true));
+        assert!(device.button(0x13C) && device.button(0x110));
+
+        port.set_light(0, 255, 0, 64).unwrap();
+        assert_eq!(mock.light(id), Some((255, 0, 64)));
+        assert!(port.set_light(1, 255, 0, 64).is_err());
+    }
+}
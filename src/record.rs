@@ -0,0 +1,448 @@
+// Recording raw controller input to a file, and playing it back.
+//
+// The file starts with the magic bytes `STCK` and a little-endian `u16`
+// version number.  Then it's a list of records, each starting with a kind
+// byte, the microseconds since the last record (`u32`) and the device's
+// native index (`u8`):
+//
+// - `0` Connect: hardware ID (`u32`), name length (`u16`), name, number of
+//   axes (`u8`), then for each axis the `ABS_*` code (`u8`) and its
+//   `AbsInfo` as six `i32`s, the number of buttons (`u16`) and their
+//   `EV_KEY` codes (`u16` each), then the unique ID length (`u16`) and
+//   unique ID.
+// - `1` Disconnect: nothing else.
+// - `2` Event: evdev type (`u16`), code (`u16`) and value (`i32`).
+
+use crate::backend::{Backend, RawInput};
+use crate::devices::{AbsInfo, DeviceInfo};
+use crate::timer::Timer;
+
+use std::io::{self, Read, Write};
+use std::task::Context;
+use std::time::{Duration, Instant};
+
+// File format identifier and version.
+const MAGIC: &[u8; 4] = b"STCK";
+const VERSION: u16 = 1;
+
+// Record kinds.
+const CONNECT: u8 = 0;
+const DISCONNECT: u8 = 1;
+const EVENT: u8 = 2;
+
+// Writes raw input from a `Port` to a file.
+pub(crate) struct Recorder {
+    out: Box<dyn Write + Send>,
+    // Time of the last record.
+    time: Instant,
+    // First write error, which stops the recording.
+    error: Option<io::Error>,
+}
+
+impl Recorder {
+    pub(crate) fn new(mut out: Box<dyn Write + Send>) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+
+        Ok(Recorder {
+            out,
+            time: Instant::now(),
+            error: None,
+        })
+    }
+
+    pub(crate) fn record(&mut self, input: &RawInput) {
+        match *input {
+            RawInput::Connect(native, ref info) => {
+                let data = encode_info(info);
+                self.write(CONNECT, native, &data);
+            }
+            RawInput::Disconnect(native) => self.write(DISCONNECT, native, &[]),
+            RawInput::Event(native, ev_type, ev_code, ev_value) => {
+                let mut data = [0; 8];
+                data[0..2].copy_from_slice(&ev_type.to_le_bytes());
+                data[2..4].copy_from_slice(&ev_code.to_le_bytes());
+                data[4..8].copy_from_slice(&ev_value.to_le_bytes());
+                self.write(EVENT, native, &data);
+            }
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.out.flush()
+    }
+
+    fn write(&mut self, kind: u8, native: usize, data: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let now = Instant::now();
+        let delta = (now - self.time).as_micros().min(u32::MAX as u128);
+        self.time = now;
+
+        let mut header = [0; 6];
+        header[0] = kind;
+        header[1..5].copy_from_slice(&(delta as u32).to_le_bytes());
+        header[5] = native as u8;
+
+        if let Err(error) = self
+            .out
+            .write_all(&header)
+            .and_then(|_| self.out.write_all(data))
+        {
+            self.error = Some(error);
+        }
+    }
+}
+
+fn encode_info(info: &DeviceInfo) -> Vec<u8> {
+    let name = info.name.as_bytes();
+    let name = &name[..name.len().min(u16::MAX as usize)];
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&info.hardware_id.to_le_bytes());
+    buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    buf.extend_from_slice(name);
+    buf.push(info.abs.len() as u8);
+    for (code, abs) in info.abs.iter() {
+        buf.push(*code);
+        for v in [
+            abs.value,
+            abs.min,
+            abs.max,
+            abs.fuzz,
+            abs.flat,
+            abs.resolution,
+        ]
+        .iter()
+        {
+            buf.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    buf.extend_from_slice(&(info.keys.len() as u16).to_le_bytes());
+    for key in info.keys.iter() {
+        buf.extend_from_slice(&key.to_le_bytes());
+    }
+    let uniq = info.uniq.as_bytes();
+    let uniq = &uniq[..uniq.len().min(u16::MAX as usize)];
+    buf.extend_from_slice(&(uniq.len() as u16).to_le_bytes());
+    buf.extend_from_slice(uniq);
+    buf
+}
+
+/// A backend that plays back a recording made with `Port::record()`, so that
+/// it goes through the same mapping as live input.  This makes input
+/// deterministic, for reproducing bug reports and for testing without
+/// hardware:
+///
+/// ```norun
+/// let file = std::fs::File::open("bug-report.stick")?;
+/// let mut port = Port::with_backend(Replay::new(file)?);
+/// while let Some((id, event)) = port.try_poll() {
+///     // ...
+/// }
+/// ```
+pub struct Replay {
+    input: Box<dyn Read + Send>,
+    // Whether to wait until each record's time, rather than going as fast
+    // as possible.
+    realtime: bool,
+    // When playback started (realtime only).
+    start: Option<Instant>,
+    // Time of the last record read, since the start of the recording.
+    time: Duration,
+    // The next record, if it's been read but isn't due yet.
+    next: Option<RawInput>,
+    // Whether the end of the recording has been reached.
+    done: bool,
+    // Wakes the task polling the `Port` when the next record is due.
+    timer: Timer,
+}
+
+impl Replay {
+    /// Open a recording.  Fails if it's not a recording, or is from a newer
+    /// version of stick.
+    pub fn new<R: Read + Send + 'static>(input: R) -> io::Result<Self> {
+        let mut input = Box::new(input);
+        let mut header = [0; 6];
+        input.read_exact(&mut header)?;
+
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a stick recording",
+            ));
+        }
+        if u16::from_le_bytes([header[4], header[5]]) > VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unsupported stick recording version",
+            ));
+        }
+
+        Ok(Replay {
+            input,
+            realtime: false,
+            start: None,
+            time: Duration::default(),
+            next: None,
+            done: false,
+            timer: Timer::new(),
+        })
+    }
+
+    /// Play back at the speed it was recorded, instead of as fast as
+    /// possible.
+    pub fn realtime(mut self) -> Self {
+        self.realtime = true;
+        self
+    }
+
+    // How long until the next record is due, reading it if needed.  `None`
+    // at the end of the recording.
+    fn due(&mut self) -> Option<Duration> {
+        if self.next.is_none() && !self.done {
+            match self.read_record() {
+                Ok(record) => self.next = Some(record),
+                // End of recording, or corrupt from here on.
+                Err(_) => self.done = true,
+            }
+        }
+        self.next.as_ref()?;
+
+        if !self.realtime {
+            return Some(Duration::from_secs(0));
+        }
+        let start = *self.start.get_or_insert_with(Instant::now);
+        Some((start + self.time).saturating_duration_since(Instant::now()))
+    }
+
+    fn read_record(&mut self) -> io::Result<RawInput> {
+        let mut header = [0; 6];
+        self.input.read_exact(&mut header)?;
+
+        let delta =
+            u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+        let native = header[5] as usize;
+        self.time += Duration::from_micros(delta.into());
+
+        Ok(match header[0] {
+            CONNECT => {
+                let hardware_id = self.read_u32()?;
+                let mut name = vec![0; self.read_u16()? as usize];
+                self.input.read_exact(&mut name)?;
+                let mut abs = Vec::new();
+                for _ in 0..self.read_u8()? {
+                    let code = self.read_u8()?;
+                    let info = AbsInfo {
+                        value: self.read_u32()? as i32,
+                        min: self.read_u32()? as i32,
+                        max: self.read_u32()? as i32,
+                        fuzz: self.read_u32()? as i32,
+                        flat: self.read_u32()? as i32,
+                        resolution: self.read_u32()? as i32,
+                    };
+                    abs.push((code, info));
+                }
+                let mut keys = Vec::new();
+                for _ in 0..self.read_u16()? {
+                    keys.push(self.read_u16()?);
+                }
+                let mut uniq = vec![0; self.read_u16()? as usize];
+                self.input.read_exact(&mut uniq)?;
+
+                let info = DeviceInfo {
+                    hardware_id,
+                    name: String::from_utf8_lossy(&name).to_string(),
+                    uniq: String::from_utf8_lossy(&uniq).to_string(),
+                    abs,
+                    keys,
+                    path: String::new(),
+                };
+                RawInput::Connect(native, info)
+            }
+            DISCONNECT => RawInput::Disconnect(native),
+            EVENT => RawInput::Event(
+                native,
+                self.read_u16()?,
+                self.read_u16()?,
+                self.read_u32()? as i32,
+            ),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unknown record kind",
+                ))
+            }
+        })
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.input.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut buf = [0; 2];
+        self.input.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.input.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+}
+
+impl Backend for Replay {
+    fn poll(&mut self, timeout: Option<Duration>, input: &mut Vec<RawInput>) {
+        let due = if let Some(due) = self.due() {
+            due
+        } else {
+            // Nothing more will ever come.
+            match timeout {
+                Some(timeout) => std::thread::sleep(timeout),
+                None => loop {
+                    std::thread::park();
+                },
+            }
+            return;
+        };
+        if due > Duration::from_secs(0) {
+            match timeout {
+                Some(timeout) if timeout < due => {
+                    std::thread::sleep(timeout);
+                    return;
+                }
+                _ => std::thread::sleep(due),
+            }
+        }
+
+        // Give the `Port` one frame at a time, so device state can be
+        // checked after each event.
+        while self.due() == Some(Duration::from_secs(0)) {
+            let record = self.next.take().unwrap();
+            let end = match record {
+                RawInput::Event(_, ev_type, ev_code, _) => {
+                    ev_type == 0x00 && ev_code == 0
+                }
+                _ => true,
+            };
+            input.push(record);
+            if end {
+                break;
+            }
+        }
+    }
+
+    fn wake_on_input(&mut self, cx: &mut Context<'_>) -> bool {
+        match self.due() {
+            None => false,
+            Some(due) if due == Duration::from_secs(0) => true,
+            Some(due) => {
+                self.timer.wake_at(Instant::now() + due, cx.waker());
+                false
+            }
+        }
+    }
+
+    fn finished(&mut self) -> bool {
+        self.due().is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Btn, Event, Port};
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Waker};
+
+    // A `Write` that can be read back after the `Recorder` is done.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn record_replay() {
+        let out = Shared::default();
+        let info = DeviceInfo {
+            hardware_id: 0x_054C_0268,
+            name: "Sony PLAYSTATION(R)3 Controller".to_string(),
+            uniq: "00:11:22:33:44:55".to_string(),
+            abs: vec![(
+                0,
+                AbsInfo {
+                    min: 0,
+                    max: 255,
+                    ..Default::default()
+                },
+            )],
+            keys: vec![0x130, 0x131],
+            // Where it was isn't recorded.
+            path: String::new(),
+        };
+
+        let mut recorder = Recorder::new(Box::new(out.clone())).unwrap();
+        recorder.record(&RawInput::Connect(3, info.clone()));
+        recorder.record(&RawInput::Event(3, 0x01, 0x130, 1));
+        recorder.record(&RawInput::Event(3, 0x00, 0, 0));
+        recorder.record(&RawInput::Disconnect(3));
+        recorder.finish().unwrap();
+
+        let data = out.0.lock().unwrap().clone();
+        let replay = Replay::new(io::Cursor::new(data)).unwrap();
+        let mut port = Port::with_backend(replay);
+
+        assert_eq!(port.try_poll(), Some((0, Event::Connect)));
+        assert_eq!(port.get(0).unwrap().info(), &info);
+        assert_eq!(port.try_poll(), Some((0, Event::Cancel(true))));
+        assert_eq!(port.get(0).unwrap().btn(Btn::B), Some(true));
+        assert_eq!(port.try_poll(), Some((0, Event::Disconnect)));
+        assert_eq!(port.try_poll(), None);
+        assert_eq!(port.count(), 0);
+    }
+
+    #[test]
+    fn replay_ends() {
+        let out = Shared::default();
+        let mut recorder = Recorder::new(Box::new(out.clone())).unwrap();
+        recorder.record(&RawInput::Connect(0, DeviceInfo::default()));
+        recorder.record(&RawInput::Disconnect(0));
+        recorder.finish().unwrap();
+
+        let data = out.0.lock().unwrap().clone();
+        let mut port =
+            Port::with_backend(Replay::new(io::Cursor::new(data)).unwrap());
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut next = || Pin::new(&mut port).poll_next(&mut cx);
+        assert_eq!(next(), Poll::Ready(Some((0, Event::Connect))));
+        assert_eq!(next(), Poll::Ready(Some((0, Event::Disconnect))));
+        // The `Stream` ends, and blocking polls don't wait.
+        assert_eq!(next(), Poll::Ready(None));
+        assert_eq!(port.poll_blocking(None), None);
+    }
+
+    #[test]
+    fn not_a_recording() {
+        let data = io::Cursor::new(b"RIFF\0\0".to_vec());
+        assert!(Replay::new(data).is_err());
+    }
+}
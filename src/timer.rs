@@ -0,0 +1,133 @@
+// Waking tasks at a deadline, for gestures and realtime `Replay`s.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::task::Waker;
+use std::time::Instant;
+
+// A deadline to wake a task at, on the timer thread.  Each `Timer` has at
+// most one, and setting a new one replaces it.
+#[derive(Debug)]
+pub(crate) struct Timer(u64);
+
+impl Timer {
+    pub(crate) fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        Timer(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    // Wake the task at `deadline`, instead of at any earlier deadline.
+    pub(crate) fn wake_at(&self, deadline: Instant, waker: &Waker) {
+        let timers = timers();
+        let mut entries = timers.entries.lock().unwrap();
+
+        if let Some(entry) = entries.iter_mut().find(|(id, ..)| *id == self.0) {
+            entry.1 = deadline;
+            entry.2.clone_from(waker);
+        } else {
+            entries.push((self.0, deadline, waker.clone()));
+        }
+        timers.changed.notify_one();
+    }
+
+    // Don't wake the task after all.
+    pub(crate) fn cancel(&self) {
+        let mut entries = timers().entries.lock().unwrap();
+
+        entries.retain(|(id, ..)| *id != self.0);
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// A thread that wakes tasks when their deadline passes.  Works with any
+// async executor.
+struct Timers {
+    // The deadline and task of each `Timer`.
+    entries: Mutex<Vec<(u64, Instant, Waker)>>,
+    // Notified when a deadline is set.
+    changed: Condvar,
+}
+
+impl Timers {
+    fn run(&self) -> ! {
+        let mut entries = self.entries.lock().unwrap();
+
+        loop {
+            let now = Instant::now();
+            entries.retain(|(_, deadline, waker)| {
+                let waiting = *deadline > now;
+                if !waiting {
+                    waker.wake_by_ref();
+                }
+                waiting
+            });
+
+            let next = entries.iter().map(|(_, deadline, _)| *deadline).min();
+            entries = match next {
+                Some(next) => {
+                    let wait = next.saturating_duration_since(now);
+                    self.changed.wait_timeout(entries, wait).unwrap().0
+                }
+                None => self.changed.wait(entries).unwrap(),
+            };
+        }
+    }
+}
+
+// Get the timers, starting their thread the first time.
+fn timers() -> &'static Timers {
+    static TIMERS: OnceLock<Timers> = OnceLock::new();
+
+    TIMERS.get_or_init(|| {
+        std::thread::Builder::new()
+            .name("stick-timer".to_string())
+            .spawn(|| timers().run())
+            .expect("Couldn't spawn timer thread!");
+
+        Timers {
+            entries: Mutex::new(Vec::new()),
+            changed: Condvar::new(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::time::Duration;
+
+    // Sends its name when it's woken.
+    struct Named(Mutex<Sender<&'static str>>, &'static str);
+
+    impl Wake for Named {
+        fn wake(self: Arc<Self>) {
+            let _ = self.0.lock().unwrap().send(self.1);
+        }
+    }
+
+    #[test]
+    fn deadlines() {
+        let (tx, rx) = mpsc::channel();
+        let waker =
+            |name| Waker::from(Arc::new(Named(Mutex::new(tx.clone()), name)));
+        let (a, b) = (Timer::new(), Timer::new());
+        let soon = Instant::now() + Duration::from_millis(20);
+
+        // Replaced, and cancelled.
+        a.wake_at(soon, &waker("old"));
+        a.wake_at(soon, &waker("a"));
+        b.wake_at(soon, &waker("b"));
+        b.cancel();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("a"));
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
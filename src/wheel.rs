@@ -0,0 +1,368 @@
+use crate::{AbsInfo, AxisKind, Device, DeviceInfo};
+
+// Absolute axes used by wheels.
+const ABS_X: u8 = 0x00;
+const ABS_Y: u8 = 0x01;
+const ABS_Z: u8 = 0x02;
+const ABS_RZ: u8 = 0x05;
+const ABS_WHEEL: u8 = 0x08;
+const ABS_GAS: u8 = 0x09;
+const ABS_BRAKE: u8 = 0x0A;
+
+/// How the pedals of a wheel are reported.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pedals {
+    /// Each pedal has its own axis (`ABS_*` code).  Pedals that rest at the
+    /// maximum of their range are inverted.
+    Separate {
+        /// Throttle (gas) pedal axis.
+        throttle: Pedal,
+        /// Brake pedal axis.
+        brake: Pedal,
+        /// Clutch pedal axis, if there is one.
+        clutch: Option<Pedal>,
+    },
+    /// Throttle and brake share one axis that rests in the middle, with
+    /// throttle towards the maximum (or the minimum, if inverted).
+    Combined(Pedal),
+}
+
+/// A pedal axis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Pedal {
+    /// The `ABS_*` code.
+    pub code: u8,
+    /// Whether it rests at the maximum instead of the minimum.
+    pub inverted: bool,
+}
+
+impl Pedal {
+    fn new(code: u8, inverted: bool) -> Self {
+        Pedal { code, inverted }
+    }
+
+    // Get how far the pedal is pressed, 0.0 to 1.0.
+    fn value(self, device: &Device) -> Option<f32> {
+        let abs = device.abs(self.code)?;
+        let value = unipolar(&abs);
+        Some(if self.inverted { 1.0 - value } else { value })
+    }
+}
+
+/// The layout of a racing wheel, for reading steering, pedals and shifters
+/// from a `Device`:
+///
+/// ```norun
+/// let device = port.get(id).unwrap();
+/// if let Some(wheel) = Wheel::new(device.info()) {
+///     let wheel = wheel.range(270.0);
+///     car.steer(wheel.steering(device));
+///     car.accelerate(wheel.throttle(device));
+/// }
+/// ```
+///
+/// Pedals are unipolar: they go from 0.0 at rest to 1.0 pressed all the
+/// way, without the centered deadzone that sticks get.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wheel {
+    // Steering axis.
+    steer: u8,
+    // Degrees the wheel turns from lock to lock.
+    rotation: f32,
+    // Degrees from lock to lock that map to -1.0 to 1.0.
+    range: f32,
+    pedals: Pedals,
+    // Paddle shifter buttons (`EV_KEY` codes): up, down.
+    paddles: Option<(u16, u16)>,
+    // H-shifter buttons (`EV_KEY` codes), and their gears (-1 is reverse).
+    gears: Vec<(u16, i8)>,
+}
+
+impl Wheel {
+    /// Get the layout of a wheel, or `None` if the device isn't one.  Known
+    /// wheels get their own layout; others use `ABS_WHEEL` (or `ABS_X`) to
+    /// steer and `ABS_GAS` and `ABS_BRAKE` pedals.
+    pub fn new(info: &DeviceInfo) -> Option<Wheel> {
+        match info.hardware_id {
+            // Logitech Driving Force GT, G25, G27, G29 and G920 report
+            // inverted pedals, and their buttons in order from BTN_TRIGGER.
+            0x_046D_C29A | 0x_046D_C299 | 0x_046D_C29B | 0x_046D_C24F
+            | 0x_046D_C262 => Some(Wheel {
+                steer: ABS_X,
+                rotation: 900.0,
+                range: 900.0,
+                pedals: Pedals::Separate {
+                    throttle: Pedal::new(ABS_Z, true),
+                    brake: Pedal::new(ABS_RZ, true),
+                    clutch: Some(Pedal::new(ABS_Y, true)),
+                },
+                paddles: Some((0x124, 0x125)),
+                // Buttons 12 to 18; past 16 they go on from
+                // BTN_TRIGGER_HAPPY.
+                gears: vec![
+                    (0x12C, 1),
+                    (0x12D, 2),
+                    (0x12E, 3),
+                    (0x12F, 4),
+                    (0x2C0, 5),
+                    (0x2C1, 6),
+                    (0x2C2, -1),
+                ],
+            }),
+            _ => {
+                let steer = if info.abs(ABS_WHEEL).is_some() {
+                    ABS_WHEEL
+                } else {
+                    ABS_X
+                };
+                info.abs(steer)?;
+                info.abs(ABS_GAS)?;
+                info.abs(ABS_BRAKE)?;
+                let pedal = |code| {
+                    Pedal::new(code, info.axis_kind(code) == AxisKind::Inverted)
+                };
+
+                Some(Wheel {
+                    steer,
+                    rotation: 900.0,
+                    range: 900.0,
+                    pedals: Pedals::Separate {
+                        throttle: pedal(ABS_GAS),
+                        brake: pedal(ABS_BRAKE),
+                        clutch: None,
+                    },
+                    paddles: None,
+                    gears: Vec::new(),
+                })
+            }
+        }
+    }
+
+    /// Set how many degrees the wheel physically turns from lock to lock
+    /// (900 by default).  This also resets `range()`.
+    pub fn rotation(mut self, degrees: f32) -> Self {
+        self.rotation = degrees;
+        self.range = degrees;
+        self
+    }
+
+    /// Set how many degrees, from lock to lock, `steering()` covers.  For a
+    /// car that steers 270°, turning the wheel 135° either way is full lock,
+    /// and turning it further does nothing.
+    pub fn range(mut self, degrees: f32) -> Self {
+        self.range = degrees.min(self.rotation);
+        self
+    }
+
+    /// Set how the pedals are reported.
+    pub fn pedals(mut self, pedals: Pedals) -> Self {
+        self.pedals = pedals;
+        self
+    }
+
+    /// Set the buttons (`EV_KEY` codes) of the paddle shifters.
+    pub fn paddles(mut self, up: u16, down: u16) -> Self {
+        self.paddles = Some((up, down));
+        self
+    }
+
+    /// Set the buttons (`EV_KEY` codes) of the H-shifter, with their gears
+    /// (-1 for reverse).
+    pub fn gears(mut self, gears: &[(u16, i8)]) -> Self {
+        self.gears = gears.to_vec();
+        self
+    }
+
+    /// Get the steering, from -1.0 (left) to 1.0 (right) across `range()`.
+    pub fn steering(&self, device: &Device) -> f32 {
+        let abs = if let Some(abs) = device.abs(self.steer) {
+            abs
+        } else {
+            return 0.0;
+        };
+        let value = unipolar(&abs) * 2.0 - 1.0;
+        if self.range <= 0.0 {
+            return 0.0;
+        }
+        (value * self.rotation / self.range).clamp(-1.0, 1.0)
+    }
+
+    /// Get how far the throttle is pressed, from 0.0 to 1.0.
+    pub fn throttle(&self, device: &Device) -> f32 {
+        match self.pedals {
+            Pedals::Separate { throttle, .. } => {
+                throttle.value(device).unwrap_or(0.0)
+            }
+            Pedals::Combined(pedal) => combined(pedal, device).max(0.0),
+        }
+    }
+
+    /// Get how far the brake is pressed, from 0.0 to 1.0.
+    pub fn brake(&self, device: &Device) -> f32 {
+        match self.pedals {
+            Pedals::Separate { brake, .. } => {
+                brake.value(device).unwrap_or(0.0)
+            }
+            Pedals::Combined(pedal) => (-combined(pedal, device)).max(0.0),
+        }
+    }
+
+    /// Get how far the clutch is pressed, from 0.0 to 1.0, or `None` if
+    /// there's no clutch.
+    pub fn clutch(&self, device: &Device) -> Option<f32> {
+        match self.pedals {
+            Pedals::Separate { clutch, .. } => clutch?.value(device),
+            Pedals::Combined(_) => None,
+        }
+    }
+
+    /// Get the throttle minus the brake, from -1.0 to 1.0, for games that
+    /// use one axis for both.
+    pub fn combined(&self, device: &Device) -> f32 {
+        self.throttle(device) - self.brake(device)
+    }
+
+    /// Check if the shift up paddle is held.
+    pub fn shift_up(&self, device: &Device) -> bool {
+        self.paddles.is_some_and(|(up, _)| device.button(up))
+    }
+
+    /// Check if the shift down paddle is held.
+    pub fn shift_down(&self, device: &Device) -> bool {
+        self.paddles.is_some_and(|(_, down)| device.button(down))
+    }
+
+    /// Get the gear the H-shifter is in (-1 for reverse), or `None` for
+    /// neutral.
+    pub fn gear(&self, device: &Device) -> Option<i8> {
+        let raw = device.raw();
+        self.gears
+            .iter()
+            .find(|(code, _)| raw.key(*code))
+            .map(|(_, gear)| *gear)
+    }
+}
+
+// Scale a raw axis value from its range to 0.0 through 1.0.
+fn unipolar(abs: &AbsInfo) -> f32 {
+    if abs.max <= abs.min {
+        return 0.0;
+    }
+    let range = (abs.max as f32) - (abs.min as f32);
+    (((abs.value as f32) - (abs.min as f32)) / range).clamp(0.0, 1.0)
+}
+
+// Get a combined pedal axis, from -1.0 (brake) to 1.0 (throttle).
+fn combined(pedal: Pedal, device: &Device) -> f32 {
+    let value = device.abs(pedal.code).map(|abs| unipolar(&abs) * 2.0 - 1.0);
+    let value = value.unwrap_or(0.0);
+    if pedal.inverted {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{drain, mock_port};
+
+    fn range(min: i32, max: i32) -> AbsInfo {
+        AbsInfo {
+            min,
+            max,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn g29() {
+        let (mock, mut port) = mock_port();
+        let info = DeviceInfo {
+            hardware_id: 0x_046D_C24F,
+            abs: vec![
+                (ABS_X, range(0, 65535)),
+                (ABS_Y, range(0, 255)),
+                (ABS_Z, range(0, 255)),
+                (ABS_RZ, range(0, 255)),
+            ],
+            ..Default::default()
+        };
+        let id = mock.connect(info.clone());
+        // Throttle floored (the pedals are inverted), brake and clutch at
+        // rest, wheel turned 45° right.
+        for (code, value) in [(ABS_X, 36044), (ABS_Y, 255), (ABS_Z, 0)] {
+            mock.event(id, 0x03, code as u16, value);
+        }
+        mock.event(id, 0x03, ABS_RZ as u16, 255);
+        mock.event(id, 0x01, 0x124, 1);
+        mock.event(id, 0x01, 0x2C2, 1);
+        mock.sync(id);
+        drain(&mut port);
+
+        let device = port.get(0).unwrap();
+        let wheel = Wheel::new(&info).unwrap();
+        assert!((wheel.steering(device) - 0.1).abs() < 0.001);
+        let wheel = wheel.range(90.0);
+        assert!((wheel.steering(device) - 1.0).abs() < 0.001);
+        assert_eq!(wheel.throttle(device), 1.0);
+        assert_eq!(wheel.brake(device), 0.0);
+        assert_eq!(wheel.clutch(device), Some(0.0));
+        assert_eq!(wheel.combined(device), 1.0);
+        assert!(wheel.shift_up(device));
+        assert!(!wheel.shift_down(device));
+        assert_eq!(wheel.gear(device), Some(-1));
+    }
+
+    #[test]
+    fn generic() {
+        let gamepad = DeviceInfo {
+            abs: vec![(ABS_X, range(-128, 127)), (ABS_Y, range(-128, 127))],
+            ..Default::default()
+        };
+        assert_eq!(Wheel::new(&gamepad), None);
+
+        let (mock, mut port) = mock_port();
+        let info = DeviceInfo {
+            abs: vec![
+                (ABS_WHEEL, range(-450, 450)),
+                (ABS_GAS, range(0, 1023)),
+                (ABS_BRAKE, range(0, 1023)),
+            ],
+            ..Default::default()
+        };
+        let id = mock.connect(info.clone());
+        mock.event(id, 0x03, ABS_WHEEL as u16, -450);
+        mock.event(id, 0x03, ABS_BRAKE as u16, 1023);
+        mock.sync(id);
+        drain(&mut port);
+
+        let device = port.get(0).unwrap();
+        let wheel = Wheel::new(&info).unwrap();
+        assert_eq!(wheel.steering(device), -1.0);
+        assert_eq!(wheel.brake(device), 1.0);
+        assert_eq!(wheel.combined(device), -1.0);
+        assert_eq!(wheel.clutch(device), None);
+        assert_eq!(wheel.gear(device), None);
+
+        let wheel = wheel.pedals(Pedals::Combined(Pedal::new(ABS_GAS, true)));
+        assert_eq!(wheel.throttle(device), 1.0);
+        assert_eq!(wheel.brake(device), 0.0);
+    }
+
+    #[test]
+    fn force_feedback() {
+        let (mock, mut port) = mock_port();
+        let id = mock.connect(DeviceInfo::default());
+        port.try_poll();
+
+        port.set_gain(0, 0.5).unwrap();
+        port.set_autocenter(0, 1.0).unwrap();
+        assert_eq!(
+            mock.output(id),
+            [(0x15, 0x60, 0x7FFF), (0x15, 0x61, 0xFFFF)]
+        );
+        assert!(port.set_gain(1, 0.5).is_err());
+    }
+}
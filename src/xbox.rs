@@ -0,0 +1,124 @@
+// Xbox 360, One and Series controllers, as `xpad` (USB and the wireless
+// receiver) and `xpadneo` (Bluetooth) report them.  The face buttons are by
+// label, so A is on the south.
+
+use crate::devices::{Btn, RawState};
+use crate::Event;
+
+// Triggers are axes only, 0 to 255 (360) or 0 to 1023 (One and Series).
+pub(crate) const ABS_Z: u8 = 0x02;
+pub(crate) const ABS_RZ: u8 = 0x05;
+
+// The D-pad, when `xpad` reports it as buttons instead of a hat (the 360
+// wireless receiver, or `dpad_to_buttons`).
+const BTN_TRIGGER_HAPPY1: u16 = 0x2C0;
+const BTN_TRIGGER_HAPPY2: u16 = 0x2C1;
+const BTN_TRIGGER_HAPPY3: u16 = 0x2C2;
+const BTN_TRIGGER_HAPPY4: u16 = 0x2C3;
+
+// The Share button (Series X|S).
+const KEY_RECORD: u16 = 0xA7;
+// The Elite paddles: upper right, lower right, upper left, lower left.
+const PADDLES: [u16; 4] = [0x2C4, 0x2C5, 0x2C6, 0x2C7];
+
+// Check if a device is an Xbox controller.
+pub(crate) fn xbox(hardware_id: u32) -> bool {
+    matches!(
+        hardware_id,
+        // Third-party (PDP) wired controller.
+        0x_0E6F_0501
+        // 360: wired, Play & Charge and the wireless receiver.
+        | 0x_045E_028E | 0x_045E_028F | 0x_045E_0719
+        // One: original, S (USB and Bluetooth) and Elite.
+        | 0x_045E_02D1 | 0x_045E_02DD | 0x_045E_02EA | 0x_045E_02E0
+        | 0x_045E_02FD | 0x_045E_02E3
+        // Elite Series 2 (USB and Bluetooth).
+        | 0x_045E_0B00 | 0x_045E_0B05 | 0x_045E_0B22
+        // Series X|S (USB and Bluetooth).
+        | 0x_045E_0B12 | 0x_045E_0B13
+    )
+}
+
+// Get the D-pad direction of a button, for D-pads reported as buttons.
+pub(crate) fn dpad(code: u16) -> Option<Btn> {
+    match code {
+        BTN_TRIGGER_HAPPY1 => Some(Btn::Left),
+        BTN_TRIGGER_HAPPY2 => Some(Btn::Right),
+        BTN_TRIGGER_HAPPY3 => Some(Btn::Up),
+        BTN_TRIGGER_HAPPY4 => Some(Btn::Down),
+        _ => None,
+    }
+}
+
+// Get the events of the Share button and paddles, which don't have a `Btn`.
+pub(crate) fn events(
+    old: &RawState,
+    new: &RawState,
+    mut event: impl FnMut(Event),
+) {
+    if old.key(KEY_RECORD) != new.key(KEY_RECORD) {
+        event(Event::Share(new.key(KEY_RECORD)));
+    }
+    for (paddle, &code) in PADDLES.iter().enumerate() {
+        if old.key(code) != new.key(code) {
+            event(Event::Paddle(paddle as u8, new.key(code)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{drain, mock_port};
+    use crate::{AbsInfo, AxisKind, DeviceInfo};
+
+    #[test]
+    fn xbox() {
+        let (mock, mut port) = mock_port();
+        let trigger = AbsInfo {
+            min: 0,
+            max: 1023,
+            ..Default::default()
+        };
+        // Series X|S, with the wireless receiver's button D-pad.
+        let id = mock.connect(DeviceInfo {
+            hardware_id: 0x_045E_0B12,
+            abs: vec![(ABS_Z, trigger), (ABS_RZ, trigger)],
+            keys: vec![0xA7, 0x130, 0x13C, 0x2C0, 0x2C1, 0x2C2, 0x2C3, 0x2C4],
+            ..Default::default()
+        });
+        drain(&mut port);
+        assert_eq!(port.get(0).unwrap().axis_kind(ABS_Z), AxisKind::Unipolar);
+
+        mock.abs(id, ABS_RZ.into(), 1023);
+        mock.key(id, BTN_TRIGGER_HAPPY3, true);
+        drain(&mut port);
+        let device = port.get(0).unwrap();
+        assert_eq!(device.lrt(), Some((0.0, 1.0)));
+        assert_eq!(device.btn(Btn::R), Some(true));
+        assert_eq!(device.btn(Btn::Up), Some(true));
+
+        // Share, Guide and the Elite paddles have their own events, and are
+        // held as raw buttons.
+        for &code in [KEY_RECORD, 0x13C, PADDLES[0], PADDLES[3]].iter() {
+            mock.key(id, code, true);
+        }
+        let events = drain(&mut port);
+        assert_eq!(
+            events,
+            [
+                (0, Event::Share(true)),
+                (0, Event::Exit),
+                (0, Event::Paddle(0, true)),
+                (0, Event::Paddle(3, true)),
+            ]
+        );
+        let device = port.get(0).unwrap();
+        assert!(device.button(KEY_RECORD) && device.button(0x13C));
+        assert!(device.button(PADDLES[0]) && device.button(PADDLES[3]));
+        mock.key(id, 0x13C, false);
+        mock.key(id, PADDLES[3], false);
+        let events = drain(&mut port);
+        assert_eq!(events, [(0, Event::Paddle(3, false))]);
+    }
+}